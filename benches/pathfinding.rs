@@ -0,0 +1,162 @@
+use std::collections::BinaryHeap;
+use std::cmp::Ordering;
+use std::time::{Instant, Duration};
+use rand::{Rng, thread_rng};
+use rand::rngs::ThreadRng;
+use abyssal_zone_server::astar::{astar, walkable, Ai, Position};
+use abyssal_zone_server::flowfield::FlowField;
+use abyssal_zone_server::generator::GeneratorKind;
+use abyssal_zone_server::navgraph::{JumpSettings, NavGraph};
+use abyssal_zone_server::tilemap::Tilemap;
+use abyssal_zone_server::vector::Vector;
+
+const QUERIES: usize = 200;
+
+#[derive(Clone, Eq)]
+struct LegacyNode {
+    parent: Option<usize>,
+    position: Position,
+    g: usize,
+    f: usize,
+}
+
+impl PartialEq for LegacyNode {
+    fn eq(&self, other: &Self) -> bool {
+        self.position == other.position
+    }
+}
+
+impl Ord for LegacyNode {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.f.cmp(&self.f)
+    }
+}
+
+impl PartialOrd for LegacyNode {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The search astar() replaced: a linear closed list and a full open list scan for every child.
+// Kept here only as the baseline the benchmarks compare against.
+fn legacy_astar(tilemap: &[Vec<usize>], start: Position, end: Position, ai_type: &Ai) -> Option<Vec<Vector>> {
+    let start_time = Instant::now();
+    let run_duration = Duration::from_millis(10);
+    let mut open_list: BinaryHeap<LegacyNode> = BinaryHeap::new();
+    let mut closed_list: Vec<LegacyNode> = vec![];
+    open_list.push(LegacyNode { parent: None, position: start, g: 0, f: 0 });
+    while let Some(current_node) = open_list.pop() {
+        if Instant::now().duration_since(start_time) > run_duration {
+            break;
+        }
+        let current_index: usize = closed_list.len();
+        closed_list.push(current_node.clone());
+        if current_node.position == end {
+            let mut path: Vec<Vector> = vec![];
+            let mut current: Option<usize> = Some(current_index);
+            while let Some(index) = current {
+                path.push(Vector::from(closed_list[index].position.clone()));
+                current = closed_list[index].parent;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for adjacent in current_node.position.get_adjacent(tilemap, ai_type) {
+            if !walkable(tilemap, &current_node.position, &adjacent, ai_type) { continue; }
+            let h: usize = ((adjacent.x as isize - end.x as isize).pow(2) + (adjacent.y as isize - end.y as isize).pow(2)) as usize;
+            let child = LegacyNode { parent: Some(current_index), position: adjacent, g: current_node.g + 1, f: current_node.g + 1 + h };
+            if closed_list.contains(&child) { continue; }
+            if open_list.iter().any(|n| n == &child && n.g < child.g) { continue; }
+            open_list.push(child);
+        }
+    }
+    None
+}
+
+struct Timing {
+    total: Duration,
+    worst: Duration,
+    found: usize
+}
+
+impl Timing {
+    fn new() -> Self {
+        Timing { total: Duration::ZERO, worst: Duration::ZERO, found: 0 }
+    }
+
+    fn record(&mut self, elapsed: Duration, found: bool) {
+        self.total += elapsed;
+        self.worst = self.worst.max(elapsed);
+        if found {
+            self.found += 1;
+        }
+    }
+
+    fn report(&self, label: &str, queries: usize) {
+        let mean: f64 = self.total.as_secs_f64() * 1_000_000f64 / queries.max(1) as f64;
+        println!("    {label:<8} mean {mean:>10.1}us  worst {:>10.1}us  found {}/{}", self.worst.as_secs_f64() * 1_000_000f64, self.found, queries);
+    }
+}
+
+fn random_open_tile(tilemap: &Tilemap, rng: &mut ThreadRng) -> Position {
+    let room: usize = rng.gen_range(0..tilemap.rooms.len());
+    let tiles: Vec<[usize; 2]> = tilemap.standable_tiles(room);
+    if tiles.is_empty() {
+        return Position::from(tilemap.spawn_locations[room]);
+    }
+    Position::from(tiles[rng.gen_range(0..tiles.len())])
+}
+
+// Times astar() against the old search on random queries between rooms of freshly generated maps.
+// Run with `cargo bench`.
+fn main() {
+    let mut rng: ThreadRng = thread_rng();
+    for kind in [GeneratorKind::Rooms, GeneratorKind::Caves, GeneratorKind::Bsp] {
+        let generator = kind.build();
+        for depth in 0..4 {
+            let tilemap: Tilemap = generator.generate(depth);
+            if tilemap.rooms.is_empty() { continue; }
+            let queries: Vec<(Position, Position)> = (0..QUERIES).map(|_| (random_open_tile(&tilemap, &mut rng), random_open_tile(&tilemap, &mut rng))).collect();
+            for (name, ai) in [("spider", Ai::Spider), ("ground", Ai::Ground)] {
+                println!("{} depth {depth} ({}x{}) {name}:", kind.name(), tilemap.tilemap.first().map_or(0, |row| row.len()), tilemap.tilemap.len());
+                let mut current: Timing = Timing::new();
+                let mut legacy: Timing = Timing::new();
+                for (start, end) in &queries {
+                    let begin: Instant = Instant::now();
+                    let found: bool = astar(&tilemap.tilemap, start.clone(), end.clone(), &ai).is_some();
+                    current.record(begin.elapsed(), found);
+                    let begin: Instant = Instant::now();
+                    let found: bool = legacy_astar(&tilemap.tilemap, start.clone(), end.clone(), &ai).is_some();
+                    legacy.record(begin.elapsed(), found);
+                }
+                current.report("astar", queries.len());
+                legacy.report("legacy", queries.len());
+
+                // Every query heading for the first target, as a crowd chasing one player would.
+                let target: Position = queries[0].1.clone();
+                let mut shared: Timing = Timing::new();
+                let begin: Instant = Instant::now();
+                let field: FlowField = FlowField::new(&tilemap.tilemap, target, &ai);
+                shared.record(begin.elapsed(), false);
+                for (start, _) in &queries {
+                    let begin: Instant = Instant::now();
+                    let found: bool = field.path(&tilemap.tilemap, start.clone(), &ai).is_some();
+                    shared.record(begin.elapsed(), found);
+                }
+                shared.report("flow", queries.len());
+
+                if ai == Ai::Ground {
+                    let nav: NavGraph = NavGraph::new(&tilemap.tilemap, JumpSettings::default());
+                    let mut jumping: Timing = Timing::new();
+                    for (start, end) in &queries {
+                        let begin: Instant = Instant::now();
+                        let found: bool = nav.find_path(start.clone(), end.clone()).is_some();
+                        jumping.record(begin.elapsed(), found);
+                    }
+                    jumping.report("nav", queries.len());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use rand::Rng;
+use rand::rngs::ThreadRng;
+use crate::astar::{Ai, Behaviour};
+use crate::btree::{load_tree, Node};
+use crate::perception::Senses;
+use crate::projectile::Ranged;
+
+// One line per enemy type in the enemy data:
+// "<name> <sprite id> <ai> <behaviour> <max speed> <health> <damage> <attack cooldown ms> <aggro range> <spawn weight> [fov degrees] [hearing] [acceleration] [range] [projectile speed]"
+// Enemy types given a range fire projectiles at players that far away or closer.
+// Speeds are in tiles per second and acceleration in tiles per second squared.
+// An enemy type with a behaviour tree in TREE_DIRECTORY/<name>.tree uses it in place of the built-in
+// state machine, see btree.rs.
+#[derive(Clone)]
+pub struct Archetype {
+    pub name: String,
+    pub sprite: usize,
+    pub ai: Ai,
+    pub behaviour: Behaviour,
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub ranged: Option<Ranged>,
+    pub health: i32,
+    pub damage: i32,
+    pub cooldown: Duration,
+    pub senses: Senses,
+    pub weight: u32,
+    pub tree: Option<Arc<Node>>
+}
+
+const TREE_DIRECTORY: &str = "assets/data/trees";
+const DEFAULT_ACCELERATION: f32 = 20f32;
+
+fn parse_archetype(fields: &[&str]) -> Option<Archetype> {
+    if fields.len() < 10 {
+        return None;
+    }
+    let mut senses: Senses = Senses { sight: fields[8].parse::<f32>().ok()?, ..Senses::default() };
+    if let Some(fov) = fields.get(10) {
+        senses.fov = fov.parse::<f32>().ok()?.to_radians();
+    }
+    if let Some(hearing) = fields.get(11) {
+        senses.hearing = hearing.parse::<f32>().ok()?;
+    }
+    Some(Archetype {
+        name: fields[0].to_string(),
+        sprite: fields[1].parse::<usize>().ok()?,
+        ai: Ai::parse(fields[2])?,
+        behaviour: Behaviour::parse(fields[3])?,
+        max_speed: fields[4].parse::<f32>().ok()?,
+        acceleration: match fields.get(12) {
+            Some(acceleration) => acceleration.parse::<f32>().ok()?,
+            None => DEFAULT_ACCELERATION
+        },
+        ranged: match (fields.get(13), fields.get(14)) {
+            (Some(range), Some(speed)) => Some(Ranged { range: range.parse::<f32>().ok()?, speed: speed.parse::<f32>().ok()?, count: 1, spread: 0f32 }),
+            (None, None) => None,
+            _ => return None
+        },
+        health: fields[5].parse::<i32>().ok()?,
+        damage: fields[6].parse::<i32>().ok()?,
+        cooldown: Duration::from_millis(fields[7].parse::<u64>().ok()?),
+        senses,
+        weight: fields[9].parse::<u32>().ok()?,
+        tree: None
+    })
+}
+
+pub fn load_archetypes(filename: &str) -> Vec<Archetype> {
+    let mut archetypes: Vec<Archetype> = vec![];
+    match read_to_string(filename) {
+        Ok(file_string) => {
+            for line in file_string.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.is_empty() { continue; }
+                match parse_archetype(&fields) {
+                    Some(mut archetype) => {
+                        let tree_file: String = format!("{TREE_DIRECTORY}/{}.tree", archetype.name);
+                        if Path::new(&tree_file).exists() {
+                            archetype.tree = load_tree(&tree_file).map(Arc::new);
+                        }
+                        archetypes.push(archetype);
+                    }
+                    None => eprintln!("ERROR! Invalid enemy definition: {line}")
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+    archetypes
+}
+
+// Picks an archetype with probability proportional to its spawn weight.
+pub fn choose_archetype<'a>(archetypes: &'a [Archetype], rng: &mut ThreadRng) -> Option<&'a Archetype> {
+    let total: u32 = archetypes.iter().map(|archetype| archetype.weight).sum();
+    if total == 0 {
+        return None;
+    }
+    let mut roll: u32 = rng.gen_range(0..total);
+    for archetype in archetypes {
+        if roll < archetype.weight {
+            return Some(archetype);
+        }
+        roll -= archetype.weight;
+    }
+    None
+}
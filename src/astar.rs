@@ -1,18 +1,81 @@
 use std::collections::BinaryHeap;
 use std::time::{Instant, Duration};
 use std::cmp::Ordering;
-use std::vec;
 
+use crate::tilemap::{RoomArea, DOOR_TILE};
 use crate::vector::Vector;
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Ai {
     Spider,
     Ground,
     Corridor
 }
 
-#[derive(PartialEq)]
+// Step costs in tenths of a tile, so a diagonal can cost roughly sqrt(2) of a straight step.
+#[derive(Clone, Copy)]
+pub struct MovementCosts {
+    pub straight: usize,
+    pub diagonal: usize,
+    pub climb: usize,
+    pub drop: usize
+}
+
+impl Ai {
+    // Only the Ais enemies can be given.
+    pub fn parse(name: &str) -> Option<Ai> {
+        match name {
+            "spider" => Some(Ai::Spider),
+            "ground" => Some(Ai::Ground),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ai::Spider => "spider",
+            Ai::Ground => "ground",
+            Ai::Corridor => "corridor"
+        }
+    }
+
+    pub fn costs(&self) -> MovementCosts {
+        match self {
+            Ai::Spider => MovementCosts { straight: 10, diagonal: 14, climb: 12, drop: 10 },
+            Ai::Ground => MovementCosts { straight: 10, diagonal: 14, climb: 15, drop: 8 },
+            Ai::Corridor => MovementCosts { straight: 10, diagonal: 14, climb: 10, drop: 10 }
+        }
+    }
+}
+
+impl MovementCosts {
+    pub fn step(&self, tilemap: &[Vec<usize>], old_position: &Position, new_position: &Position) -> usize {
+        let ladder: bool = tilemap[old_position.y][old_position.x] == 6 || tilemap[new_position.y][new_position.x] == 6;
+        match (old_position.x != new_position.x, old_position.y.cmp(&new_position.y)) {
+            (true, Ordering::Equal) => self.straight,
+            (true, _) => self.diagonal,
+            (false, Ordering::Less) if ladder => self.climb,
+            (false, Ordering::Greater) if ladder => self.climb,
+            (false, Ordering::Greater) => self.drop,
+            (false, _) => self.straight
+        }
+    }
+
+    // Octile distance priced with the cheapest axis step, so it never overestimates whatever mix of
+    // straight, climbing and dropping steps the real path takes.
+    fn heuristic(&self, position: &Position, end: &Position, ai: &Ai) -> usize {
+        let dx: usize = position.x.abs_diff(end.x);
+        let dy: usize = position.y.abs_diff(end.y);
+        let axis: usize = self.straight.min(self.climb).min(self.drop);
+        if *ai != Ai::Spider {
+            return axis * (dx + dy);
+        }
+        let diagonal: usize = self.diagonal.min(2 * axis);
+        diagonal * dx.min(dy) + axis * dx.abs_diff(dy)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 pub enum Behaviour {
     SeekFromAnywhere,
     AttackMoving,
@@ -20,14 +83,26 @@ pub enum Behaviour {
     AttackGroupFromClose
 }
 
-#[derive(Clone, Eq, PartialEq)]
+impl Behaviour {
+    pub fn parse(name: &str) -> Option<Behaviour> {
+        match name {
+            "seek_from_anywhere" => Some(Behaviour::SeekFromAnywhere),
+            "attack_moving" => Some(Behaviour::AttackMoving),
+            "attack_single" => Some(Behaviour::AttackSingle),
+            "attack_group_from_close" => Some(Behaviour::AttackGroupFromClose),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Hash)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
 
 pub fn is_solid(tile_id: usize) -> bool {
-    vec![1, 4].contains(&tile_id)
+    [1, 4, DOOR_TILE].contains(&tile_id)
 }
 
 impl From<[usize; 2]> for Position {
@@ -40,8 +115,9 @@ impl Position {
     pub fn new(x: usize, y: usize) -> Self {
         Position { x, y }
     }
-    fn get_adjacent(&self, tilemap: &Vec<Vec<usize>>, ai:& Ai) -> Vec<Position> {
-        let max: usize = tilemap.len();
+    pub fn get_adjacent(&self, tilemap: &[Vec<usize>], ai:& Ai) -> Vec<Position> {
+        let height: usize = tilemap.len();
+        let width: usize = tilemap[self.y].len();
         let mut adjacent: Vec<Position> = vec![];
         if self.x > 0 {
             adjacent.push(Position { x: self.x - 1, y: self.y });
@@ -49,31 +125,27 @@ impl Position {
         if self.y > 0 {
             adjacent.push(Position { x: self.x, y: self.y - 1 });
         }
-        if self.x < max - 1 {
+        if self.x < width - 1 {
             adjacent.push(Position { x: self.x + 1, y: self.y });
         }
-        if self.y < max - 1 {
+        if self.y < height - 1 {
             adjacent.push(Position { x: self.x, y: self.y + 1 });
         }
         if *ai == Ai::Spider {
-            if self.y > 0 {
-                if !is_solid(tilemap[self.y - 1][self.x]) {
-                    if self.x > 0 {
-                        adjacent.push(Position { x: self.x - 1, y: self.y - 1 });
-                    }
-                    if self.x < max - 1 {
-                        adjacent.push(Position { x: self.x + 1, y: self.y - 1 });
-                    }
+            if self.y > 0 && !is_solid(tilemap[self.y - 1][self.x]) {
+                if self.x > 0 {
+                    adjacent.push(Position { x: self.x - 1, y: self.y - 1 });
+                }
+                if self.x < width - 1 {
+                    adjacent.push(Position { x: self.x + 1, y: self.y - 1 });
                 }
             }
-            if self.y < max - 1 {
-                if !is_solid(tilemap[self.y + 1][self.x]) {
-                    if self.x < max - 1 {
-                        adjacent.push(Position { x: self.x + 1, y: self.y + 1 });
-                    }
-                    if self.x > 0 {
-                        adjacent.push(Position { x: self.x - 1, y: self.y + 1 });
-                    }
+            if self.y < height - 1 && !is_solid(tilemap[self.y + 1][self.x]) {
+                if self.x < width - 1 {
+                    adjacent.push(Position { x: self.x + 1, y: self.y + 1 });
+                }
+                if self.x > 0 {
+                    adjacent.push(Position { x: self.x - 1, y: self.y + 1 });
                 }
             }
         }
@@ -81,24 +153,19 @@ impl Position {
     }
 }
 
-#[derive(Clone, Eq)]
+// Open set entry. The heap can hold stale entries for a tile whose g-score has since improved; those
+// are skipped when popped because the tile is already marked visited.
+#[derive(Clone, Copy, Eq, PartialEq)]
 struct Node {
-    parent: Option<usize>,
-    position: Position,
+    index: usize,
     g: usize,
-    h: usize,
     f: usize,
 }
 
-impl PartialEq for Node {
-    fn eq(&self, other: &Self) -> bool {
-        self.position == other.position
-    }
-}
-
 impl Ord for Node {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.f.cmp(&self.f) // Inverted for min-heap
+        // Inverted for min-heap, ties go to the node furthest along.
+        other.f.cmp(&self.f).then(self.g.cmp(&other.g))
     }
 }
 
@@ -108,115 +175,224 @@ impl PartialOrd for Node {
     }
 }
 
-impl Node {
-    fn set_g_and_h(&mut self, g: usize, h: usize) {
-        self.g = g;
-        self.h = h;
-        self.f = g + h;
+struct Bitset {
+    words: Vec<u64>
+}
+
+impl Bitset {
+    fn new(size: usize) -> Self {
+        Bitset { words: vec![0; size.div_ceil(64)] }
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.words[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.words[index / 64] |= 1 << (index % 64);
     }
 }
 
-fn walkable(tilemap: &Vec<Vec<usize>>, old_position: &Position, new_position: &Position, ai: &Ai) -> bool {
+pub fn walkable(tilemap: &[Vec<usize>], old_position: &Position, new_position: &Position, ai: &Ai) -> bool {
     if *ai == Ai::Corridor { return true; }
     let dy: isize = new_position.y as isize - old_position.y as isize;
     if *ai == Ai::Spider { 
-        return vec![2, 5, 3, 7, 6].contains(&tilemap[new_position.y][new_position.x]); 
+        return [2, 5, 3, 7, 6].contains(&tilemap[new_position.y][new_position.x]); 
     }
     if *ai == Ai::Ground {
         if new_position.y > 0 {
             if !is_solid(tilemap[new_position.y][new_position.x]) && is_solid(tilemap[new_position.y - 1][new_position.x]) { return true; }
             if dy >= 0 && tilemap[new_position.y][new_position.x] == 6 { return true; }
-            if new_position.y > 1 && old_position.y > 0 {
-                if is_solid(tilemap[new_position.y - 2][new_position.x]) && is_solid(tilemap[old_position.y - 1][old_position.x]) {
-                    if !is_solid(tilemap[new_position.y][new_position.x]) { return true; }
-                }
+            if new_position.y > 1 && old_position.y > 0 && is_solid(tilemap[new_position.y - 2][new_position.x]) && is_solid(tilemap[old_position.y - 1][old_position.x]) && !is_solid(tilemap[new_position.y][new_position.x]) {
+                return true;
             }
         }
         if dy < 0 {
             if tilemap[new_position.y][new_position.x] == 6 {
                 return true;
             }
-            if new_position.y > 1 {
-                if is_solid(tilemap[new_position.y - 2][new_position.x]) || is_solid(tilemap[new_position.y - 1][new_position.x]) {
-                    if !is_solid(tilemap[new_position.y][new_position.x]) { return true; }
-                }
+            if new_position.y > 1 && (is_solid(tilemap[new_position.y - 2][new_position.x]) || is_solid(tilemap[new_position.y - 1][new_position.x])) && !is_solid(tilemap[new_position.y][new_position.x]) {
+                return true;
             }
         }
     }
     false
 }
 
-pub fn astar(tilemap: &Vec<Vec<usize>>, start: Position, end: Position, ai_type: &Ai) -> Option<Vec<Vector>> {
+pub fn astar(tilemap: &[Vec<usize>], start: Position, end: Position, ai_type: &Ai) -> Option<Vec<Vector>> {
+    astar_with_costs(tilemap, start, end, ai_type, &ai_type.costs())
+}
+
+pub fn astar_with_costs(tilemap: &[Vec<usize>], start: Position, end: Position, ai_type: &Ai, costs: &MovementCosts) -> Option<Vec<Vector>> {
+    search(tilemap, start, end, ai_type, costs, None).map(|(path, _)| path)
+}
+
+// Allows the tiles one step outside the area too, since corridor entrances sit on a room's edge.
+fn in_area(position: &Position, area: Option<&RoomArea>) -> bool {
+    match area {
+        Some(area) => position.x + 1 >= area.x && position.x <= area.x + area.width && position.y + 1 >= area.y && position.y <= area.y + area.height,
+        None => true
+    }
+}
+
+// Returns the path and its cost. With an area the search never leaves that room.
+pub fn search(tilemap: &[Vec<usize>], start: Position, end: Position, ai_type: &Ai, costs: &MovementCosts, area: Option<&RoomArea>) -> Option<(Vec<Vector>, usize)> {
     let start_time = Instant::now();
     let run_duration = Duration::from_millis(10);
-    let start_node = Node { parent: None, position: start.clone(), g: 0, h: 0, f: 0 };
-    let end_node = Node { parent: None, position: end.clone(), g: 0, h: 0, f: 0 };
+    let height: usize = tilemap.len();
+    let width: usize = tilemap.first().map_or(0, |row| row.len());
+    if start.x >= width || start.y >= height || end.x >= width || end.y >= height {
+        return None;
+    }
+    let index_of = |position: &Position| position.y * width + position.x;
 
+    let mut g_scores: Vec<usize> = vec![usize::MAX; width * height];
+    let mut parents: Vec<usize> = vec![usize::MAX; width * height];
+    let mut visited: Bitset = Bitset::new(width * height);
     let mut open_list: BinaryHeap<Node> = BinaryHeap::new();
-    let mut closed_list: Vec<Node> = vec![];
-    let mut _running: bool = true;
 
-    open_list.push(start_node);
+    let start_index: usize = index_of(&start);
+    let end_index: usize = index_of(&end);
+    g_scores[start_index] = 0;
+    open_list.push(Node { index: start_index, g: 0, f: costs.heuristic(&start, &end, ai_type) });
 
-    while _running {
-        if open_list.is_empty() || Instant::now().duration_since(start_time) > run_duration {
-            _running = false;
-            break;
-        }
-        let current_node = open_list.pop().unwrap();
-        let current_index = closed_list.len();
-        closed_list.push(current_node.clone());
+    while let Some(current) = open_list.pop() {
+        if visited.contains(current.index) { continue; }
+        visited.insert(current.index);
 
         // If we have found the goal.
-        if current_node.position == end_node.position {
-            let mut path: Vec<Position> = vec![];
-            let mut current_path_node: Option<&Node> = Some(&closed_list[current_index]);
-            while let Some(current) = current_path_node {
-                path.push(current.position.clone());
-                match current.parent {
-                    Some(index) => {
-                        current_path_node = Some(&closed_list[index]);
-                    }
-                    None => {
-                        current_path_node = None;
-                    }
-                }
+        if current.index == end_index {
+            let mut path: Vec<Vector> = vec![];
+            let mut index: usize = current.index;
+            while index != usize::MAX {
+                path.push(Vector::component((index % width) as f32, (index / width) as f32));
+                index = parents[index];
             }
             path.reverse();
-            let mut vec_path: Vec<Vector> = vec![];
-            for node in path {
-                vec_path.push(Vector::from(node));
-            }
-            return Some(vec_path);
-        }
-
-        // Find the children of the current node (based on adjacents).
-        let mut children: Vec<Node> = vec![];
-        for adjacent in current_node.position.get_adjacent(tilemap, ai_type) {
-            if walkable(tilemap, &current_node.position, &adjacent, ai_type) {
-                let mut new_node = Node {
-                    parent: Some(current_index),
-                    position: adjacent.clone(),
-                    g: 0,
-                    h: 0,
-                    f: 0,
-                };
-                new_node.set_g_and_h(current_node.g + 1, ((adjacent.x as isize - end_node.position.x as isize).pow(2) + (adjacent.y as isize - end_node.position.y as isize).pow(2)) as usize);
-                children.push(new_node);
+            return Some((path, current.g));
+        }
+        if Instant::now().duration_since(start_time) > run_duration {
+            break;
+        }
+
+        let position: Position = Position::new(current.index % width, current.index / width);
+        for adjacent in position.get_adjacent(tilemap, ai_type) {
+            let index: usize = index_of(&adjacent);
+            if visited.contains(index) || !in_area(&adjacent, area) || !walkable(tilemap, &position, &adjacent, ai_type) { continue; }
+            let g: usize = current.g + costs.step(tilemap, &position, &adjacent);
+            if g < g_scores[index] {
+                g_scores[index] = g;
+                parents[index] = current.index;
+                open_list.push(Node { index, g, f: g + costs.heuristic(&adjacent, &end, ai_type) });
             }
         }
+    }
+    None
+}
 
-        // Loop through children
-        for child in children {
-            // If child is not in closed list
-            if !closed_list.contains(&child) {
-                // If child is already in open list with skip adding it if it is worse
-                if let Some(_) = open_list.iter().find(|&n| n == &child && n.g < child.g) {
-                    continue;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fixtures are drawn top row first: '#' is solid, '.' open and 'H' a ladder. Row 0 of the tilemap
+    // is the bottom line.
+    fn fixture(rows: &[&str]) -> Vec<Vec<usize>> {
+        rows.iter().rev().map(|row| row.chars().map(|tile| match tile {
+            '#' => 1,
+            'H' => 6,
+            _ => 2
+        }).collect()).collect()
+    }
+
+    const STRAIGHT: [&str; 5] = [
+        "#####",
+        "#...#",
+        "#...#",
+        "#...#",
+        "#####"
+    ];
+
+    const STAIRS: [&str; 6] = [
+        "######",
+        "#....#",
+        "#...##",
+        "#..###",
+        "#.####",
+        "######"
+    ];
+
+    const LADDER: [&str; 7] = [
+        "#######",
+        "#..H..#",
+        "###H###",
+        "#..H..#",
+        "###H###",
+        "#..H..#",
+        "#######"
+    ];
+
+    const CORRIDOR: [&str; 3] = [
+        "#########",
+        "#.......#",
+        "#########"
+    ];
+
+    const DROP: [&str; 5] = [
+        "#####",
+        "#...#",
+        "#...#",
+        "##..#",
+        "#####"
+    ];
+
+    // Expected (cost, waypoints) for the spider, ground and corridor Ais in turn.
+    fn check(rows: &[&str], start: [usize; 2], end: [usize; 2], expected: [(usize, usize); 3]) {
+        let tilemap: Vec<Vec<usize>> = fixture(rows);
+        for (ai, (cost, length)) in [Ai::Spider, Ai::Ground, Ai::Corridor].into_iter().zip(expected) {
+            let (path, found): (Vec<Vector>, usize) = search(&tilemap, start.into(), end.into(), &ai, &ai.costs(), None).unwrap_or_else(|| panic!("no {} path", ai.name()));
+            assert_eq!((found, path.len()), (cost, length), "{} path", ai.name());
+        }
+    }
+
+    #[test]
+    fn straight_path() {
+        check(&STRAIGHT, [1, 1], [3, 1], [(20, 3), (20, 3), (20, 3)]);
+    }
+
+    #[test]
+    fn diagonal_path() {
+        check(&STAIRS, [1, 1], [4, 4], [(42, 4), (60, 7), (60, 7)]);
+    }
+
+    #[test]
+    fn ladder_climb() {
+        check(&LADDER, [1, 1], [5, 5], [(80, 8), (100, 9), (80, 9)]);
+    }
+
+    #[test]
+    fn drop_off_ledge() {
+        check(&DROP, [1, 2], [3, 1], [(24, 3), (28, 4), (30, 4)]);
+    }
+
+    #[test]
+    fn wide_map_path() {
+        check(&CORRIDOR, [1, 1], [7, 1], [(60, 7), (60, 7), (60, 7)]);
+    }
+
+    #[test]
+    fn heuristic_never_overestimates() {
+        for rows in [&STRAIGHT[..], &STAIRS[..], &LADDER[..], &DROP[..]] {
+            let tilemap: Vec<Vec<usize>> = fixture(rows);
+            let size: usize = tilemap.len();
+            for ai in [Ai::Spider, Ai::Ground, Ai::Corridor] {
+                let costs: MovementCosts = ai.costs();
+                for start in (0..size * size).map(|index| Position::new(index % size, index / size)) {
+                    for end in (0..size * size).map(|index| Position::new(index % size, index / size)) {
+                        let Some((_, cost)) = search(&tilemap, start.clone(), end.clone(), &ai, &costs, None) else { continue; };
+                        assert!(costs.heuristic(&start, &end, &ai) <= cost, "{} from {},{} to {},{}", ai.name(), start.x, start.y, end.x, end.y);
+                    }
                 }
-                open_list.push(child);
             }
         }
     }
-    None
 }
@@ -0,0 +1,146 @@
+use std::f32::consts::TAU;
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+use rand::rngs::ThreadRng;
+use rand::seq::SliceRandom;
+use crate::projectile::Ranged;
+use crate::tilemap::{Arena, TileChange, DOOR_TILE};
+
+// The boss data holds a "boss <name> <archetype>" line per boss, followed by its phases:
+//   phase <from health fraction> <max speed> <cooldown ms> <pattern> [summon <archetype> <count> <every ms>]
+// where the pattern is one of
+//   melee
+//   shot <range> <projectile speed>
+//   volley <count> <range> <projectile speed>    fanned across VOLLEY_SPREAD towards the target
+//   ring <count> <range> <projectile speed>      all the way round
+// A phase lasts until the boss drops to the next one's health fraction. Summons top the boss's living
+// minions back up to the count every so often.
+// Fights are broadcast as:
+//   <bs>uuid,name,state,health,max health,phase!  state is idle, fighting or defeated
+//   <ad>x,y,locked!                                 an arena door, locked 1 or 0
+
+const VOLLEY_SPREAD: f32 = TAU / 6f32;
+
+#[derive(Clone)]
+pub struct Summon {
+    pub archetype: String,
+    pub count: usize,
+    pub every: Duration
+}
+
+#[derive(Clone)]
+pub struct Phase {
+    pub from: f32,
+    pub max_speed: f32,
+    pub cooldown: Duration,
+    pub ranged: Option<Ranged>,
+    pub summon: Option<Summon>
+}
+
+#[derive(Clone)]
+pub struct BossDefinition {
+    pub name: String,
+    pub archetype: String,
+    pub phases: Vec<Phase>
+}
+
+fn parse_pattern(fields: &[&str]) -> Option<(Option<Ranged>, usize)> {
+    let number = |index: usize| fields.get(index).and_then(|field| field.parse::<f32>().ok());
+    match *fields.first()? {
+        "melee" => Some((None, 1)),
+        "shot" => Some((Some(Ranged { range: number(1)?, speed: number(2)?, count: 1, spread: 0f32 }), 3)),
+        "volley" => Some((Some(Ranged { range: number(2)?, speed: number(3)?, count: fields.get(1)?.parse::<usize>().ok()?, spread: VOLLEY_SPREAD }), 4)),
+        "ring" => Some((Some(Ranged { range: number(2)?, speed: number(3)?, count: fields.get(1)?.parse::<usize>().ok()?, spread: TAU }), 4)),
+        _ => None
+    }
+}
+
+fn parse_phase(fields: &[&str]) -> Option<Phase> {
+    if fields.len() < 5 {
+        return None;
+    }
+    let (ranged, used): (Option<Ranged>, usize) = parse_pattern(&fields[4..])?;
+    let summon: Option<Summon> = match &fields[4 + used..] {
+        [] => None,
+        ["summon", archetype, count, every] => Some(Summon {
+            archetype: archetype.to_string(),
+            count: count.parse::<usize>().ok()?,
+            every: Duration::from_millis(every.parse::<u64>().ok()?)
+        }),
+        _ => return None
+    };
+    Some(Phase {
+        from: fields[1].parse::<f32>().ok()?,
+        max_speed: fields[2].parse::<f32>().ok()?,
+        cooldown: Duration::from_millis(fields[3].parse::<u64>().ok()?),
+        ranged,
+        summon
+    })
+}
+
+pub fn load_bosses(filename: &str) -> Vec<BossDefinition> {
+    let mut bosses: Vec<BossDefinition> = vec![];
+    match read_to_string(filename) {
+        Ok(file_string) => {
+            for line in file_string.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                match (fields.as_slice(), bosses.last_mut()) {
+                    ([], _) => {}
+                    (["boss", name, archetype], _) => bosses.push(BossDefinition { name: name.to_string(), archetype: archetype.to_string(), phases: vec![] }),
+                    (["phase", ..], Some(boss)) => match parse_phase(&fields) {
+                        Some(phase) => boss.phases.push(phase),
+                        None => eprintln!("ERROR! Invalid boss phase: {line}")
+                    },
+                    _ => eprintln!("ERROR! Invalid boss definition: {line}")
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+    bosses.retain(|boss| !boss.phases.is_empty());
+    for boss in &mut bosses {
+        boss.phases.sort_by(|a, b| b.from.total_cmp(&a.from));
+    }
+    bosses
+}
+
+// One floor's boss fight. The boss waits in its arena until a player walks in, then the doors lock
+// until it dies or nobody is left inside, in which case it heals and waits again. Locked doors are
+// solid door tiles, so nothing gets through them.
+pub struct Encounter {
+    pub definition: BossDefinition,
+    pub arena: Arena,
+    pub boss: Option<usize>,
+    pub max_health: i32,
+    pub active: bool,
+    pub locked: bool,
+    pub defeated: bool,
+    pub phase: usize,
+    pub minions: Vec<usize>,
+    pub last_summon: Instant
+}
+
+impl Encounter {
+    pub fn new(arena: Arena, bosses: &[BossDefinition], rng: &mut ThreadRng) -> Option<Self> {
+        let definition: BossDefinition = bosses.choose(rng)?.clone();
+        Some(Encounter { definition, arena, boss: None, max_health: 0, active: false, locked: false, defeated: false, phase: 0, minions: vec![], last_summon: Instant::now() })
+    }
+
+    pub fn door_packets(&self) -> Vec<String> {
+        self.arena.doors.iter().map(|door| format!("<ad>{},{},{}!", door[0], door[1], self.locked as usize)).collect()
+    }
+
+    pub fn door_tiles(&self) -> Vec<TileChange> {
+        match self.locked {
+            true => self.arena.tiles.iter().map(|tile| TileChange { position: tile.position, tile: DOOR_TILE }).collect(),
+            false => self.arena.tiles.clone()
+        }
+    }
+
+    pub fn status_packet(&self, uuid: usize, health: i32) -> String {
+        let state: &str = if self.defeated { "defeated" } else if self.active { "fighting" } else { "idle" };
+        format!("<bs>{uuid},{},{state},{},{},{}!", self.definition.name, health.max(0), self.max_health, self.phase)
+    }
+}
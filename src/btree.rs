@@ -0,0 +1,275 @@
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+use crate::fsm::{Awareness, EnemyState};
+
+// Behaviour trees run an enemy each update. A tree file has one node per line, children indented under
+// their parent, and '#' starts a comment:
+//   sequence / selector       run children in order until one fails / succeeds
+//   invert / succeed          decorate their single child
+//   perceives, remembers, aware, arrived, within <tiles>, health_below <fraction>,
+//   in_state <state>..., state_for <ms>
+//                             conditions
+//   idle, patrol, investigate, chase, attack, flee, return
+//                             actions that pick that state and succeed
+//   move_to home|target|destination, hold_position
+//                             actions that head for the spawn point, the target or where a patrol or
+//                             flight is headed, or stand still. move_to target fails with no target
+//   strike                    strike at players in reach until the next update, and succeed
+//   fire                      shoot at the target until the next update. Fails without a clear shot
+// The state is sent to clients and decides how the enemy moves unless the tree moved it itself. Enemies
+// with a tree only strike and fire when it tells them to.
+
+pub enum Condition {
+    Perceives,
+    Remembers,
+    Aware,
+    Arrived,
+    Within(f32),
+    HealthBelow(f32),
+    InState(Vec<EnemyState>),
+    StateFor(Duration)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    Home,
+    Target,
+    Destination
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Movement {
+    MoveTo(Goal),
+    Hold
+}
+
+pub enum Action {
+    State(EnemyState),
+    Move(Movement),
+    Strike,
+    Fire
+}
+
+pub enum Node {
+    Sequence(Vec<Node>),
+    Selector(Vec<Node>),
+    Invert(Box<Node>),
+    Succeed(Box<Node>),
+    Condition(Condition),
+    Action(Action)
+}
+
+// What the tree gets to look at, gathered once per enemy update.
+pub struct Context<'a> {
+    pub awareness: &'a Awareness,
+    pub health: f32,
+    pub state: EnemyState,
+    pub since: Instant,
+    pub arrived: bool,
+    pub shot: bool
+}
+
+// Everything the actions the tree ran asked for. The last state and movement win.
+#[derive(Default)]
+pub struct Decision {
+    pub state: Option<EnemyState>,
+    pub movement: Option<Movement>,
+    pub strike: bool,
+    pub fire: bool
+}
+
+impl Decision {
+    fn merge(&mut self, other: Decision) {
+        self.state = other.state.or(self.state);
+        self.movement = other.movement.or(self.movement);
+        self.strike |= other.strike;
+        self.fire |= other.fire;
+    }
+}
+
+impl Condition {
+    fn parse(fields: &[&str]) -> Option<Condition> {
+        let argument: Option<&str> = fields.get(1).copied();
+        match fields[0] {
+            "perceives" => Some(Condition::Perceives),
+            "remembers" => Some(Condition::Remembers),
+            "aware" => Some(Condition::Aware),
+            "arrived" => Some(Condition::Arrived),
+            "within" => Some(Condition::Within(argument?.parse::<f32>().ok()?)),
+            "health_below" => Some(Condition::HealthBelow(argument?.parse::<f32>().ok()?)),
+            "in_state" if fields.len() > 1 => Some(Condition::InState(fields[1..].iter().map(|name| EnemyState::parse(name)).collect::<Option<Vec<EnemyState>>>()?)),
+            "state_for" => Some(Condition::StateFor(Duration::from_millis(argument?.parse::<u64>().ok()?))),
+            _ => None
+        }
+    }
+
+    fn check(&self, context: &Context) -> bool {
+        match self {
+            Condition::Perceives => matches!(context.awareness, Awareness::Perceived(_)),
+            Condition::Remembers => matches!(context.awareness, Awareness::Remembered),
+            Condition::Aware => !matches!(context.awareness, Awareness::Nothing),
+            Condition::Arrived => context.arrived,
+            Condition::Within(range) => matches!(context.awareness, Awareness::Perceived(distance) if distance <= range),
+            Condition::HealthBelow(fraction) => context.health <= *fraction,
+            Condition::InState(states) => states.contains(&context.state),
+            Condition::StateFor(duration) => context.since.elapsed() >= *duration
+        }
+    }
+}
+
+impl Action {
+    fn parse(fields: &[&str]) -> Option<Action> {
+        if let Some(state) = EnemyState::parse(fields[0]) {
+            return (fields.len() == 1).then_some(Action::State(state));
+        }
+        match (fields[0], fields.get(1).copied(), fields.len()) {
+            ("move_to", Some("home"), 2) => Some(Action::Move(Movement::MoveTo(Goal::Home))),
+            ("move_to", Some("target"), 2) => Some(Action::Move(Movement::MoveTo(Goal::Target))),
+            ("move_to", Some("destination"), 2) => Some(Action::Move(Movement::MoveTo(Goal::Destination))),
+            ("hold_position", None, 1) => Some(Action::Move(Movement::Hold)),
+            ("strike", None, 1) => Some(Action::Strike),
+            ("fire", None, 1) => Some(Action::Fire),
+            _ => None
+        }
+    }
+
+    fn run(&self, context: &Context, decision: &mut Decision) -> bool {
+        match self {
+            Action::State(state) => decision.state = Some(*state),
+            Action::Move(Movement::MoveTo(Goal::Target)) if matches!(context.awareness, Awareness::Nothing) => return false,
+            Action::Move(movement) => decision.movement = Some(*movement),
+            Action::Strike => decision.strike = true,
+            Action::Fire if !context.shot => return false,
+            Action::Fire => decision.fire = true
+        }
+        true
+    }
+}
+
+impl Node {
+    fn tick(&self, context: &Context, decision: &mut Decision) -> bool {
+        match self {
+            // A sequence that fails partway leaves nothing behind from the children that did run.
+            Node::Sequence(children) => {
+                let mut scratch: Decision = Decision::default();
+                if !children.iter().all(|child| child.tick(context, &mut scratch)) {
+                    return false;
+                }
+                decision.merge(scratch);
+                true
+            }
+            Node::Selector(children) => children.iter().any(|child| child.tick(context, decision)),
+            Node::Invert(child) => !child.tick(context, decision),
+            Node::Succeed(child) => {
+                child.tick(context, decision);
+                true
+            }
+            Node::Condition(condition) => condition.check(context),
+            Node::Action(action) => action.run(context, decision)
+        }
+    }
+
+    pub fn decide(&self, context: &Context) -> Decision {
+        let mut decision: Decision = Decision::default();
+        self.tick(context, &mut decision);
+        decision
+    }
+}
+
+fn parse_node(lines: &[(usize, Vec<&str>)], index: &mut usize) -> Option<Node> {
+    let (depth, fields): &(usize, Vec<&str>) = &lines[*index];
+    *index += 1;
+    let mut children: Vec<Node> = vec![];
+    while *index < lines.len() && lines[*index].0 > *depth {
+        children.push(parse_node(lines, index)?);
+    }
+    let node: Node = match fields[0] {
+        "sequence" if !children.is_empty() => Node::Sequence(children),
+        "selector" if !children.is_empty() => Node::Selector(children),
+        "invert" if children.len() == 1 => Node::Invert(Box::new(children.pop()?)),
+        "succeed" if children.len() == 1 => Node::Succeed(Box::new(children.pop()?)),
+        _ if children.is_empty() => match Action::parse(fields) {
+            Some(action) => Node::Action(action),
+            None => Node::Condition(Condition::parse(fields)?)
+        },
+        _ => return None
+    };
+    Some(node)
+}
+
+fn parse_tree(contents: &str) -> Option<Node> {
+    let lines: Vec<(usize, Vec<&str>)> = contents.lines().filter_map(|line| {
+        let line: &str = line.split('#').next().unwrap_or("");
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() { return None; }
+        Some((line.len() - line.trim_start().len(), fields))
+    }).collect();
+    if lines.is_empty() {
+        return None;
+    }
+    let mut index: usize = 0;
+    let tree: Node = parse_node(&lines, &mut index)?;
+    (index == lines.len()).then_some(tree)
+}
+
+pub fn load_tree(filename: &str) -> Option<Node> {
+    let contents: String = match read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+            return None;
+        }
+    };
+    let tree: Option<Node> = parse_tree(&contents);
+    if tree.is_none() {
+        eprintln!("ERROR! Invalid behaviour tree: {filename}");
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decide(tree: &str, awareness: Awareness, shot: bool) -> Decision {
+        parse_tree(tree).unwrap().decide(&Context { awareness: &awareness, health: 1f32, state: EnemyState::Idle, since: Instant::now(), arrived: false, shot })
+    }
+
+    #[test]
+    fn sequence_runs_every_child() {
+        let decision: Decision = decide("sequence\n  perceives\n  strike\n  chase\n  move_to target\n", Awareness::Perceived(4f32), false);
+        assert!(decision.state == Some(EnemyState::Chase) && decision.movement == Some(Movement::MoveTo(Goal::Target)) && decision.strike);
+    }
+
+    #[test]
+    fn sequence_failing_partway_leaves_nothing() {
+        let tree: &str = "selector\n  sequence\n    strike\n    chase\n    fire\n  sequence\n    idle\n";
+        let decision: Decision = decide(tree, Awareness::Perceived(4f32), false);
+        assert!(decision.state == Some(EnemyState::Idle) && decision.movement.is_none() && !decision.strike && !decision.fire);
+        let decision: Decision = decide(tree, Awareness::Perceived(4f32), true);
+        assert!(decision.state == Some(EnemyState::Chase) && decision.strike && decision.fire);
+    }
+
+    #[test]
+    fn selector_stops_at_first_success() {
+        let tree: &str = "selector\n  sequence\n    within 1\n    attack\n  sequence\n    perceives\n    chase\n  investigate\n";
+        assert!(decide(tree, Awareness::Perceived(0.5f32), false).state == Some(EnemyState::Attack));
+        assert!(decide(tree, Awareness::Perceived(4f32), false).state == Some(EnemyState::Chase));
+        assert!(decide(tree, Awareness::Remembered, false).state == Some(EnemyState::Investigate));
+    }
+
+    #[test]
+    fn decorators_flip_and_swallow_failure() {
+        let tree: &str = "sequence\n  invert\n    aware\n  succeed\n    move_to target\n  idle\n";
+        assert!(decide(tree, Awareness::Nothing, false).state == Some(EnemyState::Idle));
+        assert!(decide(tree, Awareness::Remembered, false).state.is_none());
+    }
+
+    #[test]
+    fn malformed_trees_are_rejected() {
+        assert!(parse_tree("").is_none());
+        assert!(parse_tree("sequence\n").is_none());
+        assert!(parse_tree("invert\n  strike\n  fire\n").is_none());
+        assert!(parse_tree("strike\nfire\n").is_none());
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs::{read_to_string, File};
+use std::io::{BufWriter, Write};
+use crate::astar::is_solid;
+use crate::packet::compress_tiles;
+use crate::tilemap::EXIT_TILE;
+
+// Opt-in AI debug channel. Each enemy update produces one frame per floor:
+//   <dbgf>floor!                                           start of a frame
+//   <dbge>floor,uuid,name,ai,state,target,health,x,y,path!  path is the remaining waypoints as x:y;x:y
+//   <dbgp>floor,username,x,y!
+// Clients get frames for their floor after sending <dbg>username,1!. The log file also starts with a
+// <dbgm>floor,width,height,tiles! map per floor, tiles compressed like <tmc>, so it can be rendered.
+
+pub fn map_packet(floor: usize, tilemap: &[Vec<usize>]) -> String {
+    let height: usize = tilemap.len();
+    let width: usize = tilemap.first().map_or(0, |row| row.len());
+    let tiles: Vec<usize> = tilemap.iter().flatten().copied().collect();
+    format!("<dbgm>{floor},{width},{height},{}!", compress_tiles(&tiles))
+}
+
+pub struct DebugLog {
+    writer: BufWriter<File>
+}
+
+impl DebugLog {
+    pub fn new(filename: &str) -> Option<Self> {
+        match File::create(filename) {
+            Ok(file) => Some(DebugLog { writer: BufWriter::new(file) }),
+            Err(e) => {
+                eprintln!("ERROR! {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn write(&mut self, packets: &[String]) {
+        for packet in packets {
+            if let Err(e) = writeln!(self.writer, "{packet}") {
+                eprintln!("ERROR! {}", e);
+                return;
+            }
+        }
+        if let Err(e) = self.writer.flush() {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+}
+
+fn expand_tiles(data: &str) -> Vec<usize> {
+    let mut tiles: Vec<usize> = vec![];
+    for run in data.split(';') {
+        let (tile, count): (&str, &str) = run.split_once(':').unwrap_or((run, "1"));
+        if let (Ok(tile), Ok(count)) = (tile.parse::<usize>(), count.parse::<usize>()) {
+            tiles.extend(std::iter::repeat_n(tile, count));
+        }
+    }
+    tiles
+}
+
+fn parse_point(point: &str) -> Option<[f32; 2]> {
+    let (x, y) = point.split_once(':')?;
+    Some([x.parse::<f32>().ok()?, y.parse::<f32>().ok()?])
+}
+
+fn plot(grid: &mut [Vec<char>], point: [f32; 2], symbol: char) {
+    let (x, y): (isize, isize) = (point[0].round() as isize, point[1].round() as isize);
+    if y >= 0 && (y as usize) < grid.len() && x >= 0 && (x as usize) < grid[y as usize].len() {
+        grid[y as usize][x as usize] = symbol;
+    }
+}
+
+fn tile_symbol(tile: usize) -> char {
+    match tile {
+        6 => 'H',
+        EXIT_TILE => 'E',
+        9..=11 => '^',
+        tile if is_solid(tile) => '#',
+        _ => ' '
+    }
+}
+
+// Draws the last frame recorded for a floor in a debug log: paths as '*', enemies by the first
+// letter of their name and players as '@', with a line per enemy underneath.
+pub fn render(filename: &str, floor: usize) {
+    let contents: String = match read_to_string(filename) {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+            return;
+        }
+    };
+    let mut map: Option<(usize, Vec<usize>)> = None;
+    let mut frame: Vec<Vec<String>> = vec![];
+    for line in contents.lines() {
+        let Some((identifier, data)) = line.strip_prefix('<').and_then(|line| line.strip_suffix('!')).and_then(|line| line.split_once('>')) else { continue; };
+        let data: Vec<String> = data.split(',').map(String::from).collect();
+        if data.first().and_then(|value| value.parse::<usize>().ok()) != Some(floor) { continue; }
+        match identifier {
+            "dbgm" if data.len() == 4 => {
+                if let Ok(width) = data[1].parse::<usize>() {
+                    map = Some((width, expand_tiles(&data[3])));
+                }
+            }
+            "dbgf" => frame.clear(),
+            "dbge" | "dbgp" => frame.push(data),
+            _ => {}
+        }
+    }
+    let Some((width, tiles)) = map else {
+        eprintln!("ERROR! No map for floor {floor} in {filename}");
+        return;
+    };
+    let mut grid: Vec<Vec<char>> = tiles.chunks(width.max(1)).map(|row| row.iter().map(|tile| tile_symbol(*tile)).collect()).collect();
+    let mut legend: Vec<String> = vec![];
+    for data in &frame {
+        if data.len() == 10 {
+            let points: Vec<[f32; 2]> = data[9].split(';').filter_map(parse_point).collect();
+            for pair in points.windows(2) {
+                let steps: usize = ((pair[1][0] - pair[0][0]).abs().max((pair[1][1] - pair[0][1]).abs()).ceil() as usize).max(1);
+                for step in 0..=steps {
+                    let t: f32 = step as f32 / steps as f32;
+                    plot(&mut grid, [pair[0][0] + (pair[1][0] - pair[0][0]) * t, pair[0][1] + (pair[1][1] - pair[0][1]) * t], '*');
+                }
+            }
+            legend.push(format!("{} {} ({}) {}hp {} -> {}", data[1], data[2], data[3], data[6], data[4], data[5]));
+        }
+    }
+    for data in &frame {
+        let (symbol, x, y): (char, &str, &str) = match data.len() {
+            4 => ('@', &data[2], &data[3]),
+            10 => (data[2].chars().next().unwrap_or('?').to_ascii_uppercase(), &data[7], &data[8]),
+            _ => continue
+        };
+        if let (Ok(x), Ok(y)) = (x.parse::<f32>(), y.parse::<f32>()) {
+            plot(&mut grid, [x, y], symbol);
+        }
+        if symbol == '@' {
+            legend.push(format!("@ {}", data[1]));
+        }
+    }
+    for row in grid.iter().rev() {
+        println!("{}", row.iter().collect::<String>());
+    }
+    for line in legend {
+        println!("{line}");
+    }
+}
+
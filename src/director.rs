@@ -0,0 +1,133 @@
+use std::fs::read_to_string;
+use std::time::{Duration, Instant};
+use rand::Rng;
+use rand::rngs::ThreadRng;
+
+// One "<setting> <value>" per line in the director data. Settings left out keep their defaults.
+#[derive(Clone)]
+pub struct DirectorSettings {
+    // Enemies allowed alive at once with one player, and how many more each extra player adds.
+    pub base_cap: usize,
+    pub cap_per_player: usize,
+    pub max_cap: usize,
+    // Enemies never appear closer than this to a player, or anywhere a player can see.
+    pub min_distance: f32,
+    pub wave_interval: Duration,
+    pub wave_size: usize,
+    // Chance each enemy update of a single enemy turning up between waves.
+    pub trickle_chance: f64,
+    // Caps and wave sizes grow from 1x to max_difficulty over this much of the session.
+    pub ramp: Duration,
+    pub max_difficulty: f32,
+    // Each floor below the first adds this much difficulty on top of the ramp, and makes enemies as much
+    // tougher and harder hitting.
+    pub floor_difficulty: f32,
+    // Enemies further than despawn_distance from every player for despawn_after are removed.
+    pub despawn_distance: f32,
+    pub despawn_after: Duration,
+    // Chasing further than this outside the room an enemy spawned in sends it back.
+    pub leash_distance: f32
+}
+
+impl Default for DirectorSettings {
+    fn default() -> Self {
+        DirectorSettings {
+            base_cap: 2,
+            cap_per_player: 1,
+            max_cap: 12,
+            min_distance: 10f32,
+            wave_interval: Duration::from_millis(45000),
+            wave_size: 3,
+            trickle_chance: 0.1f64,
+            ramp: Duration::from_millis(600000),
+            max_difficulty: 2f32,
+            floor_difficulty: 0.25f32,
+            despawn_distance: 40f32,
+            despawn_after: Duration::from_millis(30000),
+            leash_distance: 30f32
+        }
+    }
+}
+
+fn apply_setting(settings: &mut DirectorSettings, fields: &[&str]) -> Option<()> {
+    let value: &str = fields.get(1)?;
+    match fields[0] {
+        "base_cap" => settings.base_cap = value.parse::<usize>().ok()?,
+        "cap_per_player" => settings.cap_per_player = value.parse::<usize>().ok()?,
+        "max_cap" => settings.max_cap = value.parse::<usize>().ok()?,
+        "min_distance" => settings.min_distance = value.parse::<f32>().ok()?,
+        "wave_interval" => settings.wave_interval = Duration::from_millis(value.parse::<u64>().ok()?),
+        "wave_size" => settings.wave_size = value.parse::<usize>().ok()?,
+        "trickle_chance" => settings.trickle_chance = value.parse::<f64>().ok()?,
+        "ramp" => settings.ramp = Duration::from_millis(value.parse::<u64>().ok()?),
+        "max_difficulty" => settings.max_difficulty = value.parse::<f32>().ok()?,
+        "floor_difficulty" => settings.floor_difficulty = value.parse::<f32>().ok()?,
+        "despawn_distance" => settings.despawn_distance = value.parse::<f32>().ok()?,
+        "despawn_after" => settings.despawn_after = Duration::from_millis(value.parse::<u64>().ok()?),
+        "leash_distance" => settings.leash_distance = value.parse::<f32>().ok()?,
+        _ => return None
+    }
+    Some(())
+}
+
+pub fn load_director_settings(filename: &str) -> DirectorSettings {
+    let mut settings: DirectorSettings = DirectorSettings::default();
+    match read_to_string(filename) {
+        Ok(file_string) => {
+            for line in file_string.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.is_empty() { continue; }
+                if apply_setting(&mut settings, &fields).is_none() {
+                    eprintln!("ERROR! Invalid director setting: {line}");
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+    settings
+}
+
+// Decides how many enemies a floor gets and when: a full wave every wave_interval, the odd straggler
+// in between, never past the population cap.
+pub struct Director {
+    pub settings: DirectorSettings,
+    depth: usize,
+    // When the session began. The ramp runs on session time, so floors reached later start harder.
+    started: Instant,
+    next_wave: Instant
+}
+
+impl Director {
+    pub fn new(settings: DirectorSettings, depth: usize, started: Instant) -> Self {
+        Director { settings, depth, started, next_wave: Instant::now() }
+    }
+
+    // How much tougher enemies are on this floor than the first.
+    pub fn strength(&self) -> f32 {
+        1f32 + self.settings.floor_difficulty * self.depth as f32
+    }
+
+    fn difficulty(&self) -> f32 {
+        let progress: f32 = (self.started.elapsed().as_secs_f32() / self.settings.ramp.as_secs_f32().max(0.001f32)).min(1f32);
+        (1f32 + (self.settings.max_difficulty - 1f32) * progress) * self.strength()
+    }
+
+    pub fn cap(&self, players: usize) -> usize {
+        let cap: usize = self.settings.base_cap + self.settings.cap_per_player * players.saturating_sub(1);
+        ((cap as f32 * self.difficulty()).round() as usize).min(self.settings.max_cap)
+    }
+
+    pub fn spawns(&mut self, alive: usize, players: usize, rng: &mut ThreadRng) -> usize {
+        let room: usize = self.cap(players).saturating_sub(alive);
+        if room == 0 {
+            return 0;
+        }
+        if Instant::now() >= self.next_wave {
+            self.next_wave = Instant::now() + self.settings.wave_interval;
+            return ((self.settings.wave_size as f32 * self.difficulty()).round() as usize).min(room);
+        }
+        if rng.gen_bool(self.settings.trickle_chance.clamp(0f64, 1f64)) { 1 } else { 0 }
+    }
+}
@@ -1,9 +1,24 @@
-use crate::astar::{astar, Ai, Behaviour, Position};
+use crate::archetype::{choose_archetype, Archetype};
+use crate::boss::{Encounter, Phase};
+use crate::btree::{Context, Decision, Goal, Movement, Node};
+use crate::director::Director;
+use crate::fsm::{Awareness, EnemyState, ATTACK_RANGE};
+use crate::astar::{Ai, Behaviour, Position};
+use crate::tilemap::{apply_changes, RoomArea, Tilemap, TileChange};
+use crate::navgraph::{standable, EdgeKind, JumpSettings, NavGraph, LADDER};
+use crate::pathservice::PathService;
+use crate::projectile::{Impact, Projectile, Ranged};
+use crate::perception::{line_of_sight, Memory, Senses, STILL};
+use crate::smoothing::line_clear;
 use crate::packet::PlayerData;
 use rand::Rng;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::f32::consts::TAU;
 use std::time::{Instant, Duration};
 use rand::{thread_rng, rngs::ThreadRng};
-use crate::astar::is_solid;
+use crate::astar::{is_solid, walkable};
 use crate::vector::Vector;
 
 fn convert_angle(angle_ccw_from_x: f32) -> f32 {
@@ -27,47 +42,247 @@ fn compass_atan(x: f32, y: f32) -> f32 {
     angle
 }
 
+// A queued path request is replaced once its goal is further than this from the target, in tiles.
+const STALE_DISTANCE: usize = 3;
+// Distances in tiles between the target and the end of the current path.
+const REPLAN_DISTANCE: usize = 3;
+const REPAIR_DISTANCE: usize = 10;
+const REPLAN_AFTER: Duration = Duration::from_millis(4000);
+// How long after moving a player still draws enemies that attack moving targets.
+const MOVING_WINDOW: Duration = Duration::from_millis(1500);
+// Random tiles tried for each spawn before the director gives up until the next update.
+const SPAWN_ATTEMPTS: usize = 10;
+// Tiles per second squared, and the fastest anything falls.
+const GRAVITY: f32 = 30f32;
+const MAX_FALL_SPEED: f32 = 20f32;
+// How far above the higher end of a jump the arc peaks.
+const JUMP_CLEARANCE: f32 = 1f32;
+// Projectiles last this many times as long as they need to cover their range.
+const PROJECTILE_LIFETIME: f32 = 1.5f32;
+const PLAYER_DAMAGE: i32 = 20;
+const PLAYER_ATTACK_RANGE: f32 = 1.5f32;
+const PLAYER_ATTACK_COOLDOWN: Duration = Duration::from_millis(400);
+
+fn has_shot(tilemap: &[Vec<usize>], from: &Vector, to: &Vector, range: f32) -> bool {
+    (to - from).magnitude <= range && line_of_sight(tilemap, from, to)
+}
+
 pub struct Enemy {
     uuid: usize,
     name: String,
     ai: Ai,
     id: usize,
     behaviour: Behaviour,
+    // Current speed along the path, building up to max_speed, in tiles per second.
     speed: f32,
+    max_speed: f32,
+    acceleration: f32,
+    fall_speed: f32,
+    ranged: Option<Ranged>,
+    health: i32,
+    max_health: i32,
+    damage: i32,
+    cooldown: Duration,
+    state: EnemyState,
+    state_since: Instant,
+    home: Vector,
+    room: usize,
+    // Where a patrolling or fleeing enemy is headed.
+    destination: Option<Vector>,
+    // Set when the enemy strays past the leash, and kept until it is home again.
+    leashed: bool,
+    far_since: Option<Instant>,
+    tree: Option<Arc<Node>>,
+    // Whether the enemy strikes at players in reach and shoots at its target. Set by the tree, or by the
+    // state for enemies without one.
+    strikes: bool,
+    fires: bool,
     position: Vector,
     old_position: Vector,
     path_index: usize,
     path: Option<Vec<Vector>>,
+    moves: Vec<EdgeKind>,
+    edge_start: Vector,
+    edge_progress: f32,
+    planned: Instant,
+    replan: bool,
+    target: Option<String>,
+    senses: Senses,
+    facing: f32,
+    memory: Option<Memory>,
+    searching: bool,
+    quarry: Option<String>,
     last_hit: Instant
 }   
 
+enum Replan {
+    Full,
+    // Keep the path up to this waypoint and search on from there.
+    Repair(usize)
+}
+
+struct PendingPath {
+    goal: Position,
+    splice: Option<usize>
+}
+
 impl Enemy {
-    fn movement(&mut self, deltatime: f32, players: &Vec<PlayerData>) -> Vec<String> {
+    fn accelerate(&mut self, deltatime: f32) {
+        self.speed = (self.speed + self.acceleration * deltatime).min(self.max_speed);
+    }
+
+    // Moves along a nav graph path, shaping the motion by the kind of edge being crossed.
+    fn follow_edge(&mut self, deltatime: f32) {
+        let Some(path) = &self.path else { return; };
+        let (Some(target), Some(kind)) = (path.get(self.path_index).cloned(), self.moves.get(self.path_index).copied()) else { return; };
+        let dx: f32 = target.x - self.edge_start.x;
+        let dy: f32 = target.y - self.edge_start.y;
+        let done: bool = match kind {
+            EdgeKind::Walk | EdgeKind::Climb => {
+                self.accelerate(deltatime);
+                let length: f32 = (dx.abs() + dy.abs()).max(0.01f32);
+                self.edge_progress = (self.edge_progress + self.speed * deltatime / length).min(1.0f32);
+                let t: f32 = self.edge_progress;
+                self.position = Vector::component(self.edge_start.x + dx * t, self.edge_start.y + dy * t);
+                self.edge_progress >= 1.0f32
+            }
+            EdgeKind::Jump => {
+                // Launched to peak JUMP_CLEARANCE above the higher end, with gravity bringing it down on
+                // the target. Progress is the time spent in the air.
+                let apex: f32 = dy.max(0f32) + JUMP_CLEARANCE;
+                let launch: f32 = (2f32 * GRAVITY * apex).sqrt();
+                let flight: f32 = launch / GRAVITY + (2f32 * (apex - dy) / GRAVITY).sqrt();
+                self.edge_progress = (self.edge_progress + deltatime).min(flight);
+                let t: f32 = self.edge_progress;
+                self.position = Vector::component(self.edge_start.x + dx * t / flight, self.edge_start.y + launch * t - 0.5f32 * GRAVITY * t * t);
+                t >= flight
+            }
+            EdgeKind::Fall => {
+                // Walk off the ledge, then drop.
+                let remaining: f32 = target.x - self.position.x;
+                if remaining.abs() > 0.001f32 {
+                    self.accelerate(deltatime);
+                    let step: f32 = self.speed * deltatime;
+                    let x: f32 = if remaining.abs() <= step { target.x } else { self.position.x + step * remaining.signum() };
+                    self.position = Vector::component(x, self.edge_start.y);
+                    false
+                } else {
+                    self.fall_speed = (self.fall_speed + GRAVITY * deltatime).min(MAX_FALL_SPEED);
+                    let y: f32 = (self.position.y - self.fall_speed * deltatime).max(target.y);
+                    self.position = Vector::component(target.x, y);
+                    y <= target.y
+                }
+            }
+        };
+        if done {
+            self.position = target.clone();
+            self.edge_start = target;
+            self.edge_progress = 0.0f32;
+            self.fall_speed = 0.0f32;
+            self.path_index += 1;
+        }
+    }
+
+    // Ground enemies with nothing to stand on and no edge to follow drop until they land.
+    fn apply_gravity(&mut self, tilemap: &[Vec<usize>], deltatime: f32) {
+        let x: usize = self.position.x.round() as usize;
+        let row: usize = self.position.y.round() as usize;
+        let on_ladder: bool = tilemap.get(row).and_then(|tiles| tiles.get(x)) == Some(&LADDER);
+        if standable(tilemap, x, row) && (on_ladder || (self.position.y - row as f32).abs() < 0.01f32) {
+            self.fall_speed = 0.0f32;
+            return;
+        }
+        self.fall_speed = (self.fall_speed + GRAVITY * deltatime).min(MAX_FALL_SPEED);
+        let fallen: f32 = (self.position.y - self.fall_speed * deltatime).max(0f32);
+        // Land on the first tile passed that can be stood on.
+        let landing: Option<usize> = (fallen.ceil() as usize..=self.position.y.floor() as usize).rev().find(|row| standable(tilemap, x, *row));
+        self.position = match landing {
+            Some(row) => {
+                self.fall_speed = 0.0f32;
+                Vector::component(self.position.x, row as f32)
+            }
+            None => Vector::component(self.position.x, fallen)
+        };
+    }
+
+    // Whether anything left on the path can no longer be crossed, checked whenever tiles change.
+    fn path_blocked(&self, tilemap: &[Vec<usize>]) -> bool {
+        let Some(path) = &self.path else { return false; };
+        for index in self.path_index.max(1)..path.len() {
+            let blocked: bool = if self.moves.is_empty() {
+                // Single grid steps come straight from the search, which allows diagonals a line
+                // through the corner would not.
+                if (&path[index] - &path[index - 1]).magnitude < 1.5f32 {
+                    let from: Position = Position::new(path[index - 1].x as usize, path[index - 1].y as usize);
+                    !walkable(tilemap, &from, &Position::new(path[index].x as usize, path[index].y as usize), &self.ai)
+                } else {
+                    !line_clear(tilemap, &path[index - 1], &path[index], &self.ai)
+                }
+            } else {
+                is_solid(tilemap[path[index].y as usize][path[index].x as usize])
+            };
+            if blocked {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn replan_reason(&self, goal: &Position) -> Option<Replan> {
+        let Some(path) = &self.path else { return Some(Replan::Full); };
+        if self.replan || self.path_index >= path.len() || self.planned.elapsed() > REPLAN_AFTER {
+            return Some(Replan::Full);
+        }
+        let end: &Vector = path.last()?;
+        let moved: usize = (end.x as usize).abs_diff(goal.x) + (end.y as usize).abs_diff(goal.y);
+        if moved <= REPLAN_DISTANCE {
+            return None;
+        }
+        if moved <= REPAIR_DISTANCE && path.len() - self.path_index > 1 {
+            return Some(Replan::Repair(path.len() - 1));
+        }
+        Some(Replan::Full)
+    }
+
+    fn movement(&mut self, deltatime: f32, players: &Vec<PlayerData>, tilemap: &[Vec<usize>]) -> Vec<String> {
         let mut packets: Vec<String> = vec![];
-        if let Some(path) = &self.path {
+        let before: Vector = self.position.clone();
+        let following: bool = self.path.as_ref().is_some_and(|path| self.path_index < path.len());
+        if self.ai == Ai::Ground && !following {
+            self.apply_gravity(tilemap, deltatime);
+        }
+        if !self.moves.is_empty() {
+            self.follow_edge(deltatime);
+        }
+        else if let Some(path) = &self.path {
             if self.path_index < path.len() {
-                let target_position: &Vector = &path[self.path_index];
-                let mut delta: Vector = &self.position - target_position;
-                if delta.magnitude <= 0.08 { self.path_index += 1; }
+                // Smoothed paths have waypoints several tiles apart, so step towards the next one and
+                // snap onto it rather than overshooting.
+                let target_position: Vector = path[self.path_index].clone();
+                let mut delta: Vector = target_position.clone() - &self.position;
+                self.accelerate(deltatime);
+                let step: f32 = self.speed * deltatime;
+                if delta.magnitude <= step.max(0.08f32) {
+                    self.position = target_position;
+                    self.path_index += 1;
+                }
                 else {
-                    if self.ai == Ai::Ground {
-                        if delta.y > 0.0f32 {
-                            delta.y *= 0.3f32;
-                        }
-                        else {
-                            delta.y *= 1.3f32;
-                        }
-                        delta.update_polar();
-                    }
                     delta.normalize();
-                    self.position += delta;
-                }
-                if self.position != self.old_position {
-                    packets.push(format!("<ep>{},{},{},{},{}!", self.uuid, self.id, self.position.x, self.position.y, self.position.direction));
+                    self.position += delta * step;
                 }
-                self.old_position = self.position.clone();
             }
         }
+        if self.position != self.old_position {
+            packets.push(format!("<ep>{},{},{},{},{}!", self.uuid, self.id, self.position.x, self.position.y, self.position.direction));
+        }
+        self.old_position = self.position.clone();
+        let motion: Vector = &self.position - &before;
+        if motion.magnitude > 0.001f32 {
+            self.facing = motion.direction;
+        }
+        if !following {
+            self.speed = 0.0f32;
+        }
         if self.old_position.magnitude == 0f32 {
             self.old_position = self.position.clone();
             packets.push(format!("<ep>{},{},{},{},{}!", self.uuid, self.id, self.position.x, self.position.y, 0f32));
@@ -79,13 +294,13 @@ impl Enemy {
                     if let Some(last_node) = path.last() {
                         let distance: f32 = (&self.position - last_node).magnitude;
                         if distance > 5f32 {
-                            self.path_index = 999;
+                            self.replan = true;
                         }
                     }
                 }
-                if Instant::now().duration_since(self.last_hit) > Duration::from_millis(1000) && distance < 0.8f32 {
-                    self.path_index = 999;
-                    let packet: String = format!("<ph>{},{}!", player.username, -20);
+                if self.strikes && Instant::now().duration_since(self.last_hit) > self.cooldown && distance < ATTACK_RANGE {
+                    self.replan = true;
+                    let packet: String = format!("<ph>{},{}!", player.username, -self.damage);
                     packets.push(packet);
                     self.last_hit = Instant::now();
                     break;
@@ -95,36 +310,56 @@ impl Enemy {
         return packets;
     }
 
-    fn spider(uuid: &mut usize, location: Vector) -> Self {
-        *uuid += 1;
-        Enemy {
-            uuid: *uuid,
-            name: String::from("spider"),
-            ai: Ai::Spider,
-            id: 0,
-            behaviour: Behaviour::AttackSingle,
-            speed: 2f32,
-            position: location.clone(),
-            old_position: location,
-            path_index: 1,
-            path: None,
-            last_hit: Instant::now()
-        }
+    fn apply_phase(&mut self, phase: &Phase) {
+        self.max_speed = phase.max_speed;
+        self.cooldown = phase.cooldown;
+        self.ranged = phase.ranged;
     }
 
-    fn goblin(uuid: &mut usize, location: Vector) -> Self {
+    // Health and damage are scaled by the floor's strength.
+    fn spawn(uuid: &mut usize, archetype: &Archetype, location: Vector, room: usize, strength: f32) -> Self {
         *uuid += 1;
+        let health: i32 = (archetype.health as f32 * strength).round() as i32;
         Enemy {
             uuid: *uuid,
-            name: String::from("goblin"),
-            ai: Ai::Ground,
-            id: 1,
-            behaviour: Behaviour::AttackGroupFromClose,
-            speed: 4f32,
+            name: archetype.name.clone(),
+            ai: archetype.ai,
+            id: archetype.sprite,
+            behaviour: archetype.behaviour,
+            speed: 0f32,
+            max_speed: archetype.max_speed,
+            acceleration: archetype.acceleration,
+            fall_speed: 0f32,
+            ranged: archetype.ranged,
+            health,
+            max_health: health,
+            damage: (archetype.damage as f32 * strength).round() as i32,
+            cooldown: archetype.cooldown,
+            state: EnemyState::Idle,
+            state_since: Instant::now(),
+            home: location.clone(),
+            room,
+            destination: None,
+            leashed: false,
+            far_since: None,
+            tree: archetype.tree.clone(),
+            strikes: archetype.tree.is_none(),
+            fires: false,
             position: location.clone(),
-            old_position: location,
+            old_position: location.clone(),
             path_index: 1,
             path: None,
+            moves: vec![],
+            edge_start: location,
+            edge_progress: 0f32,
+            planned: Instant::now(),
+            replan: false,
+            target: None,
+            senses: archetype.senses,
+            facing: 0f32,
+            memory: None,
+            searching: false,
+            quarry: None,
             last_hit: Instant::now()
         }
     }
@@ -132,125 +367,614 @@ impl Enemy {
 
 pub struct Controller {
     uuid: usize,
-    id_count: usize,
     enemies: Vec<Enemy>,
     players: Vec<PlayerData>,
     tilemap: Vec<Vec<usize>>,
     spawn_locations: Vec<[usize; 2]>,
+    rooms: Vec<RoomArea>,
+    // Standable tiles of each room, for enemies patrolling the room they spawned in.
+    patrol_points: Vec<Vec<[usize; 2]>>,
+    paths: PathService,
+    pending: HashMap<usize, PendingPath>,
+    player_movement: HashMap<String, f32>,
+    last_moved: HashMap<String, Instant>,
+    archetypes: Vec<Archetype>,
+    director: Director,
+    jump: JumpSettings,
+    projectiles: Vec<Projectile>,
+    projectile_uuid: usize,
+    encounter: Option<Encounter>,
+    attack_times: HashMap<String, Instant>,
+    // Tiles the controller itself has changed, for the floor to pass on to clients.
+    pub tile_changes: Vec<TileChange>,
     pub packets: Vec<String>
 }
 
 impl Controller {
-    pub fn new(players: Vec<PlayerData>, tilemap: Vec<Vec<usize>>, spawn_locations: Vec<[usize; 2]>) -> Self {
-        Controller { uuid: 0, id_count: 0, enemies: vec![], players, tilemap, spawn_locations, packets: vec![] }
+    pub fn new(players: Vec<PlayerData>, map: &Tilemap, archetypes: Vec<Archetype>, director: Director, jump: JumpSettings, encounter: Option<Encounter>) -> Self {
+        let nav: NavGraph = NavGraph::new(&map.tilemap, jump);
+        let paths: PathService = PathService::new(map.tilemap.clone(), map.room_graph.clone(), nav);
+        let patrol_points: Vec<Vec<[usize; 2]>> = (0..map.rooms.len()).map(|room| map.standable_tiles(room)).collect();
+        Controller { uuid: 0, enemies: vec![], players, tilemap: map.tilemap.clone(), spawn_locations: map.spawn_locations.clone(), rooms: map.rooms.clone(), patrol_points, paths, pending: HashMap::new(), player_movement: HashMap::new(), last_moved: HashMap::new(), archetypes, director, jump, projectiles: vec![], projectile_uuid: 0, encounter, attack_times: HashMap::new(), tile_changes: vec![], packets: vec![] }
+    }
+    // Doors opening or closing. Enemies whose path runs through a door that has shut plan again.
+    pub fn set_tiles(&mut self, changes: &[TileChange]) {
+        if changes.is_empty() {
+            return;
+        }
+        apply_changes(&mut self.tilemap, changes);
+        for enemy in &mut self.enemies {
+            if enemy.path_blocked(&self.tilemap) {
+                enemy.replan = true;
+            }
+        }
+        let nav: NavGraph = NavGraph::new(&self.tilemap, self.jump);
+        self.paths.update_map(self.tilemap.clone(), nav);
     }
     pub fn update_players(&mut self, players: Vec<PlayerData>) {
-        println!("Num players: {}", players.len());
+        self.player_movement = players.iter().map(|player| {
+            let moved: f32 = match self.players.iter().find(|old| old.username == player.username) {
+                Some(old) => (&player.position - &old.position).magnitude,
+                None => 0f32
+            };
+            (player.username.clone(), moved)
+        }).collect();
+        for (username, moved) in &self.player_movement {
+            if *moved >= STILL {
+                self.last_moved.insert(username.clone(), Instant::now());
+            }
+        }
+        self.last_moved.retain(|username, _| self.player_movement.contains_key(username));
         self.players = players;
     }
-    pub fn update_enemies(&mut self) -> Option<String> {
-        let mut rng: ThreadRng = thread_rng();
-        if self.enemies.len() > 0 {
-            for i in 0..self.enemies.len() {
-                println!("Enemy X: {}", self.enemies[i].position.x);
-                let start: Position = Position::new(self.enemies[i].position.x.round() as usize, (self.enemies[i].position.y).round() as usize);
-                let mut min_dist: f32 = -1.0f32;
-                let mut closest_player_index: usize = 999;
-                for (index, player) in self.players.iter().enumerate() {
-                    let distance: f32 = (&player.position - &self.enemies[i].position).magnitude;
-                    if distance < min_dist || min_dist == -1.0f32 {
-                        min_dist = distance;
-                        closest_player_index = index;
+    // Only players the enemy can see or hear are considered. If none are, it heads for wherever it last
+    // noticed someone until that memory fades.
+    fn choose_target(&mut self, i: usize) -> Option<(String, Vector)> {
+        if self.enemies[i].behaviour == Behaviour::SeekFromAnywhere {
+            return self.seek(i);
+        }
+        let enemy: &Enemy = &self.enemies[i];
+        let mut min_dist: f32 = -1.0f32;
+        let mut closest_player_index: usize = 999;
+        for (index, player) in self.players.iter().enumerate() {
+            // Standing still keeps a player hidden from enemies that only notice movement.
+            if enemy.behaviour == Behaviour::AttackMoving && self.last_moved.get(&player.username).is_none_or(|time| time.elapsed() >= MOVING_WINDOW) { continue; }
+            let moved: f32 = self.player_movement.get(&player.username).copied().unwrap_or(0f32);
+            if !enemy.senses.can_see(&self.tilemap, &enemy.position, enemy.facing, player) && !enemy.senses.can_hear(&enemy.position, player, moved) { continue; }
+            let distance: f32 = (&player.position - &enemy.position).magnitude;
+            if distance < min_dist || min_dist == -1.0f32 {
+                min_dist = distance;
+                closest_player_index = index;
+            }
+        }
+        if closest_player_index == 999 {
+            let enemy: &mut Enemy = &mut self.enemies[i];
+            enemy.searching = enemy.memory.as_ref().is_some_and(|memory| enemy.senses.remembers(memory));
+            if !enemy.searching {
+                enemy.memory = None;
+            }
+            return enemy.memory.as_ref().map(|memory| (memory.username.clone(), memory.position.clone()));
+        }
+        if self.enemies[i].behaviour == Behaviour::AttackSingle {
+            let name: String = self.players[closest_player_index].username.clone();
+            for player in &self.players {
+                if player.username != name {
+                    let dist: f32 = (&self.players[closest_player_index].position - &player.position).magnitude;
+                    if dist <= 5.0f32 {
+                        closest_player_index = 999;
+                        break;
                     }
                 }
-                if closest_player_index != 999 && self.enemies[i].behaviour == Behaviour::AttackSingle {
-                    let name: String = self.players[closest_player_index].username.clone();
-                    for player in &self.players {
-                        if player.username != name {
-                            let dist: f32 = (&self.players[closest_player_index].position - &player.position).magnitude;
-                            if dist <= 5.0f32 {
-                                closest_player_index = 999;
-                                break;
-                            }
-                        }
-                    }
+            }
 
-                }
-                if closest_player_index != 999 && self.enemies[i].behaviour == Behaviour::AttackGroupFromClose {
-                    let name: String = self.players[closest_player_index].username.clone();
-                    let mut close_players: usize = 0;
-                    for player in &self.players {
-                        if player.username != name {
-                            let dist: f32 = (&self.players[closest_player_index].position - &player.position).magnitude;
-                            if dist <= 5.0f32 {
-                                close_players += 1;
-                            }
-                        }
-                    }
-                    if close_players <= 3 {
-                        closest_player_index = 999;
+        }
+        if closest_player_index != 999 && self.enemies[i].behaviour == Behaviour::AttackGroupFromClose {
+            let name: String = self.players[closest_player_index].username.clone();
+            let mut close_players: usize = 0;
+            for player in &self.players {
+                if player.username != name {
+                    let dist: f32 = (&self.players[closest_player_index].position - &player.position).magnitude;
+                    if dist <= 5.0f32 {
+                        close_players += 1;
                     }
                 }
-                if closest_player_index != 999 {
-                    let end: Position = Position::new(self.players[closest_player_index].position.x.round() as usize, self.players[closest_player_index].position.y.round() as usize);
-                    if self.enemies[i].path == None {
-                        self.enemies[i].path = astar(&self.tilemap, start, end, &self.enemies[i].ai);
-                        self.enemies[i].path_index = 1;
-                    }
-                    else if self.enemies[i].path_index >= self.enemies[i].path.clone().unwrap().len() {
-                        self.enemies[i].path = astar(&self.tilemap, start, end, &self.enemies[i].ai);
-                        self.enemies[i].path_index = 1; 
+            }
+            if close_players <= 3 {
+                closest_player_index = 999;
+            }
+        }
+        let enemy: &mut Enemy = &mut self.enemies[i];
+        enemy.searching = false;
+        if closest_player_index == 999 {
+            enemy.memory = None;
+            return None;
+        }
+        let player: &PlayerData = &self.players[closest_player_index];
+        enemy.memory = Some(Memory { username: player.username.clone(), position: player.position.clone(), time: Instant::now() });
+        Some((player.username.clone(), player.position.clone()))
+    }
+    // Hunters pick the closest player once and then follow them anywhere on the floor, seen or not.
+    fn seek(&mut self, i: usize) -> Option<(String, Vector)> {
+        let enemy: &Enemy = &self.enemies[i];
+        let quarry: Option<&PlayerData> = match &enemy.quarry {
+            Some(username) => self.players.iter().find(|player| &player.username == username),
+            None => None
+        };
+        let quarry: &PlayerData = match quarry {
+            Some(player) => player,
+            None => self.players.iter().min_by(|a, b| (&a.position - &enemy.position).magnitude.total_cmp(&(&b.position - &enemy.position).magnitude))?
+        };
+        let target: (String, Vector) = (quarry.username.clone(), quarry.position.clone());
+        self.enemies[i].quarry = Some(target.0.clone());
+        Some(target)
+    }
+    // Returns the uuids of any enemies the director spawned.
+    pub fn update_enemies(&mut self) -> Vec<String> {
+        let mut rng: ThreadRng = thread_rng();
+        self.paths.begin_update();
+        for result in self.paths.results() {
+            let splice: Option<usize> = self.pending.remove(&result.enemy).and_then(|pending| pending.splice);
+            let Some(enemy) = self.enemies.iter_mut().find(|enemy| enemy.uuid == result.enemy) else { continue; };
+            enemy.replan = false;
+            enemy.planned = Instant::now();
+            match (result.route, splice, &mut enemy.path) {
+                // A repaired tail is only usable while the enemy has not yet reached the splice point.
+                (Some(route), Some(splice), Some(path)) if splice >= enemy.path_index && splice < path.len() => {
+                    path.truncate(splice + 1);
+                    path.extend(route.path.into_iter().skip(1));
+                    if !enemy.moves.is_empty() {
+                        enemy.moves.truncate(splice + 1);
+                        enemy.moves.extend(route.moves.into_iter().skip(1));
                     }
                 }
+                (Some(_), Some(_), _) => {
+                    enemy.replan = true;
+                }
+                (Some(route), None, _) => {
+                    enemy.path = Some(route.path);
+                    enemy.moves = route.moves;
+                    enemy.path_index = 1;
+                    enemy.edge_start = enemy.position.clone();
+                    enemy.edge_progress = 0f32;
+                }
+                (None, _, _) => {
+                    enemy.path = None;
+                    enemy.moves.clear();
+                    enemy.path_index = 1;
+                }
             }
         }
-        if self.enemies.len() < 1 {
-            let frame_probability: f64 = 5f64 * 0.02f64;
-            let random_value: f64 = rng.gen();
-            let result: bool = random_value < frame_probability;
-            if result || true {
-                if self.spawn_locations.len() > 0 {
-                    self.id_count += 1;
-                    let mut location: Option<[usize; 2]> = None;
-                    for _ in 0..10 {
-                        let index: usize = rng.gen_range(0..self.spawn_locations.len());
-                        if index < self.spawn_locations.len() {
-                            let spawn_location: [usize; 2] = self.spawn_locations[index];
-                            let mut valid: bool = true;
-                            for player in &self.players {
-                                let distance: f32 = (Vector::from(spawn_location) * -1f32 + &player.position).magnitude;
-                                if distance <= 10.0f32 {
-                                    valid = false;
-                                }
-                            }
-                            valid = true;
-                            if valid {
-                                location = Some(spawn_location);
-                                break;
-                            }
-                        }
-                    }
-                    if let Some(location) = location {
-                        if !is_solid(self.tilemap[location[1]][location[0]]) && location[0] != 0 && location[1] != 0 {
-                            if rng.gen_bool(0.5f64) {
-                                self.enemies.push(Enemy::goblin(&mut self.uuid, location.into()));
-                            }
-                            else {
-                                self.enemies.push(Enemy::spider(&mut self.uuid, location.into()));
-                            }
-                            if let Some(e) = self.enemies.last() {
-                                    return Some(e.uuid.to_string());
-                            }
-                        }
-                    }
+        let mut spawned: Vec<String> = self.update_boss(&mut rng);
+        self.update_arena_doors();
+        for i in 0..self.enemies.len() {
+            let uuid: usize = self.enemies[i].uuid;
+            // Bosses wait for someone to walk into their arena.
+            if self.encounter.as_ref().is_some_and(|encounter| encounter.boss == Some(uuid) && !encounter.active) { continue; }
+            let target: Option<(String, Vector)> = self.choose_target(i);
+            let enemy: &Enemy = &self.enemies[i];
+            let awareness: Awareness = match &target {
+                None => Awareness::Nothing,
+                Some(_) if enemy.searching => Awareness::Remembered,
+                Some((_, position)) => Awareness::Perceived((position - &enemy.position).magnitude)
+            };
+            // A path has come back since the state began and the enemy has reached its end, or there was none.
+            let arrived: bool = !self.pending.contains_key(&uuid) && enemy.planned > enemy.state_since && enemy.path.as_ref().is_none_or(|path| enemy.path_index >= path.len());
+            let health: f32 = enemy.health as f32 / enemy.max_health as f32;
+            let decision: Option<Decision> = enemy.tree.as_ref().map(|tree| {
+                let shot: bool = enemy.ranged.is_some_and(|ranged| target.as_ref().is_some_and(|(_, position)| has_shot(&self.tilemap, &enemy.position, position, ranged.range)));
+                tree.decide(&Context { awareness: &awareness, health, state: enemy.state, since: enemy.state_since, arrived, shot })
+            });
+            let state: EnemyState = match &decision {
+                Some(decision) => decision.state.unwrap_or(enemy.state),
+                None => enemy.state.next(&awareness, health, enemy.state_since, arrived)
+            };
+            let outside: f32 = match self.rooms.get(enemy.room) {
+                Some(room) => room.distance(&enemy.position),
+                None => (&enemy.position - &enemy.home).magnitude
+            };
+            let strayed: bool = enemy.behaviour != Behaviour::SeekFromAnywhere && outside > self.director.settings.leash_distance;
+            let leashed: bool = enemy.leashed || (strayed && matches!(state, EnemyState::Chase | EnemyState::Investigate | EnemyState::Attack));
+            let state: EnemyState = match (leashed, enemy.state) {
+                (false, _) => state,
+                (true, EnemyState::Return) if arrived => EnemyState::Idle,
+                (true, _) => EnemyState::Return
+            };
+            self.enemies[i].leashed = leashed && state == EnemyState::Return;
+            let enemy: &Enemy = &self.enemies[i];
+            if state != enemy.state {
+                let threat: Option<Vector> = target.as_ref().map(|(_, position)| position.clone());
+                self.change_state(i, state, threat, &mut rng);
+            }
+            let enemy: &mut Enemy = &mut self.enemies[i];
+            enemy.target = target.as_ref().map(|(username, _)| username.clone());
+            // Leashed enemies head home as their state has them, whatever the tree says.
+            let orders: Option<&Decision> = decision.as_ref().filter(|_| !leashed);
+            let movement: Option<Movement> = orders.and_then(|orders| orders.movement);
+            (enemy.strikes, enemy.fires) = match orders {
+                Some(orders) => (orders.strike, orders.fire),
+                None => (state != EnemyState::Flee, matches!(state, EnemyState::Chase | EnemyState::Attack))
+            };
+            // Enemies heading for where a player is right now can share a flow field towards them.
+            let chased: Option<String> = target.as_ref().filter(|_| !enemy.searching).map(|(username, _)| username.clone());
+            let (position, chasing): (Vector, bool) = match (movement, state, target) {
+                (Some(Movement::Hold), _, _) => {
+                    enemy.path = None;
+                    enemy.moves.clear();
+                    continue;
+                }
+                (Some(Movement::MoveTo(Goal::Home)), _, _) => (enemy.home.clone(), false),
+                (Some(Movement::MoveTo(Goal::Target)), _, Some((_, position))) => (position, true),
+                (Some(Movement::MoveTo(Goal::Destination)), _, _) => match &enemy.destination {
+                    Some(destination) => (destination.clone(), false),
+                    None => continue
+                },
+                // Ranged enemies stop where they have a shot.
+                (None, EnemyState::Chase, Some((_, position))) if enemy.ranged.is_some_and(|ranged| has_shot(&self.tilemap, &enemy.position, &position, ranged.range)) => {
+                    enemy.path = None;
+                    enemy.moves.clear();
+                    continue;
                 }
+                (None, EnemyState::Chase | EnemyState::Investigate, Some((_, position))) => (position, true),
+                (None, EnemyState::Return, _) => (enemy.home.clone(), false),
+                (None, EnemyState::Patrol | EnemyState::Flee, _) => match &enemy.destination {
+                    Some(destination) => (destination.clone(), false),
+                    None => continue
+                },
+                // Attacking enemies finish the path they are on and idle ones have nowhere to be.
+                _ => continue
+            };
+            let goal: Position = Position::new(position.x.round() as usize, position.y.round() as usize);
+            let Some(reason) = self.enemies[i].replan_reason(&goal) else { continue; };
+            // Still waiting on a search for roughly this goal, so leave it be.
+            if let Some(pending) = self.pending.get(&uuid) {
+                if pending.goal.x.abs_diff(goal.x) + pending.goal.y.abs_diff(goal.y) <= STALE_DISTANCE { continue; }
+            }
+            let (start, splice): (Position, Option<usize>) = match (reason, &self.enemies[i].path) {
+                (Replan::Repair(splice), Some(path)) => (Position::new(path[splice].x as usize, path[splice].y as usize), Some(splice)),
+                _ => (Position::new(self.enemies[i].position.x.round() as usize, self.enemies[i].position.y.round() as usize), None)
+            };
+            self.paths.request(uuid, start, goal.clone(), self.enemies[i].ai, chased.filter(|_| chasing));
+            self.pending.insert(uuid, PendingPath { goal, splice });
+        }
+        if self.players.is_empty() {
+            return spawned;
+        }
+        for _ in 0..self.director.spawns(self.enemies.len(), self.players.len(), &mut rng) {
+            let Some((location, room)) = self.spawn_location(&mut rng) else { break; };
+            let Some(archetype) = choose_archetype(&self.archetypes, &mut rng) else { break; };
+            self.enemies.push(Enemy::spawn(&mut self.uuid, archetype, location.into(), room, self.director.strength()));
+            spawned.push(self.uuid.to_string());
+        }
+        spawned
+    }
+    // Runs the boss fight: spawns the boss, locks the arena when a player walks in, moves through the
+    // phases as its health drops and summons minions. Returns the uuids of anything it spawned.
+    fn update_boss(&mut self, rng: &mut ThreadRng) -> Vec<String> {
+        let mut spawned: Vec<String> = vec![];
+        let Some(encounter) = &mut self.encounter else { return spawned; };
+        if encounter.defeated {
+            return spawned;
+        }
+        let Some(uuid) = encounter.boss else {
+            let Some(archetype) = self.archetypes.iter().find(|archetype| archetype.name == encounter.definition.archetype) else {
+                eprintln!("ERROR! No enemy type {} for boss {}", encounter.definition.archetype, encounter.definition.name);
+                encounter.defeated = true;
+                return spawned;
+            };
+            let mut boss: Enemy = Enemy::spawn(&mut self.uuid, archetype, encounter.arena.spawn.into(), encounter.arena.room, self.director.strength());
+            boss.apply_phase(&encounter.definition.phases[0]);
+            encounter.max_health = boss.max_health;
+            self.enemies.push(boss);
+            encounter.boss = Some(self.uuid);
+            spawned.push(self.uuid.to_string());
+            return spawned;
+        };
+        let Some(boss) = self.enemies.iter_mut().find(|enemy| enemy.uuid == uuid) else {
+            // Killed, which resolve_attacks has already announced.
+            encounter.defeated = true;
+            encounter.active = false;
+            self.packets.push(encounter.status_packet(uuid, 0));
+            return spawned;
+        };
+        let inside: bool = self.players.iter().any(|player| encounter.arena.contains(&player.position));
+        if inside && !encounter.active {
+            encounter.active = true;
+            encounter.last_summon = Instant::now();
+        } else if !inside && encounter.active {
+            encounter.active = false;
+            encounter.phase = 0;
+            boss.health = boss.max_health;
+            boss.apply_phase(&encounter.definition.phases[0]);
+            boss.path = None;
+            boss.moves.clear();
+        }
+        if encounter.active {
+            let health: f32 = boss.health as f32 / boss.max_health as f32;
+            let phase: usize = encounter.definition.phases.iter().rposition(|phase| phase.from >= health).unwrap_or(0);
+            if phase != encounter.phase {
+                encounter.phase = phase;
+                boss.apply_phase(&encounter.definition.phases[phase]);
+            }
+        }
+        self.packets.push(encounter.status_packet(uuid, boss.health));
+        if !encounter.active {
+            return spawned;
+        }
+        let Some(summon) = &encounter.definition.phases[encounter.phase].summon else { return spawned; };
+        if encounter.last_summon.elapsed() < summon.every {
+            return spawned;
+        }
+        encounter.last_summon = Instant::now();
+        encounter.minions.retain(|minion| self.enemies.iter().any(|enemy| enemy.uuid == *minion));
+        let Some(archetype) = self.archetypes.iter().find(|archetype| archetype.name == summon.archetype) else { return spawned; };
+        for _ in encounter.minions.len()..summon.count {
+            let location: [usize; 2] = match self.patrol_points.get(encounter.arena.room).and_then(|points| points.choose(rng)) {
+                Some(point) => *point,
+                None => encounter.arena.spawn
+            };
+            self.enemies.push(Enemy::spawn(&mut self.uuid, archetype, location.into(), encounter.arena.room, self.director.strength()));
+            encounter.minions.push(self.uuid);
+            spawned.push(self.uuid.to_string());
+        }
+        spawned
+    }
+    // Shuts the arena while its fight is on and opens it again once the fight is over.
+    fn update_arena_doors(&mut self) {
+        let Some(encounter) = &mut self.encounter else { return; };
+        if encounter.locked == encounter.active {
+            return;
+        }
+        encounter.locked = encounter.active;
+        self.packets.append(&mut encounter.door_packets());
+        let changes: Vec<TileChange> = encounter.door_tiles();
+        self.set_tiles(&changes);
+        self.tile_changes.extend(changes);
+    }
+    // The arena doors and the boss's status, for players joining the floor.
+    pub fn encounter_packets(&self) -> Vec<String> {
+        let Some(encounter) = &self.encounter else { return vec![]; };
+        let Some(uuid) = encounter.boss else { return vec![]; };
+        let health: i32 = self.enemies.iter().find(|enemy| enemy.uuid == uuid).map_or(0, |boss| boss.health);
+        let mut packets: Vec<String> = encounter.door_packets();
+        packets.push(encounter.status_packet(uuid, health));
+        packets
+    }
+    // A random standable tile far enough from every player and out of their sight.
+    fn spawn_location(&self, rng: &mut ThreadRng) -> Option<([usize; 2], usize)> {
+        if self.spawn_locations.is_empty() {
+            return None;
+        }
+        for _ in 0..SPAWN_ATTEMPTS {
+            let room: usize = rng.gen_range(0..self.spawn_locations.len());
+            if self.encounter.as_ref().is_some_and(|encounter| encounter.arena.room == room) { continue; }
+            let location: [usize; 2] = match self.patrol_points.get(room).and_then(|points| points.choose(rng)) {
+                Some(point) => *point,
+                None => self.spawn_locations[room]
+            };
+            let position: Vector = Vector::from(location);
+            if is_solid(self.tilemap[location[1]][location[0]]) { continue; }
+            let hidden: bool = self.players.iter().all(|player| {
+                (&player.position - &position).magnitude >= self.director.settings.min_distance && !line_of_sight(&self.tilemap, &player.position, &position)
+            });
+            if hidden {
+                return Some((location, room));
             }
         }
         None
     }
+    // Picks where the new state is headed and tells clients so they can animate it.
+    fn change_state(&mut self, i: usize, state: EnemyState, threat: Option<Vector>, rng: &mut ThreadRng) {
+        let enemy: &mut Enemy = &mut self.enemies[i];
+        enemy.state = state;
+        enemy.state_since = Instant::now();
+        enemy.replan = true;
+        enemy.destination = match (state, threat) {
+            (EnemyState::Patrol, _) => self.patrol_points.get(enemy.room).and_then(|points| points.choose(rng)).map(|point| Vector::from(*point)),
+            // Run for whichever room's spawn point is furthest from the threat.
+            (EnemyState::Flee, Some(threat)) => self.spawn_locations.iter().map(|location| Vector::from(*location)).max_by(|a, b| (a - &threat).magnitude.total_cmp(&(b - &threat).magnitude)),
+            _ => None
+        };
+        self.packets.push(format!("<es>{},{}!", enemy.uuid, state.name()));
+    }
+    // One frame of the debug channel, see debug.rs.
+    pub fn debug_packets(&self, floor: usize) -> Vec<String> {
+        let mut packets: Vec<String> = vec![format!("<dbgf>{floor}!")];
+        for enemy in &self.enemies {
+            let state: String = if self.pending.contains_key(&enemy.uuid) {
+                format!("{}/pathing", enemy.state.name())
+            } else {
+                enemy.state.name().to_string()
+            };
+            let path: String = match &enemy.path {
+                Some(path) => path.iter().skip(enemy.path_index.saturating_sub(1)).map(|point| format!("{}:{}", point.x, point.y)).collect::<Vec<String>>().join(";"),
+                None => String::new()
+            };
+            let target: &str = enemy.target.as_deref().unwrap_or("NONE");
+            packets.push(format!("<dbge>{floor},{},{},{},{state},{target},{},{},{},{path}!", enemy.uuid, enemy.name, enemy.ai.name(), enemy.health, enemy.position.x, enemy.position.y));
+        }
+        for player in &self.players {
+            packets.push(format!("<dbgp>{floor},{},{},{}!", player.username, player.position.x, player.position.y));
+        }
+        packets
+    }
+    // Removes enemies that have been far from every player for too long. Returns their uuids, which have
+    // already been announced with <edis>.
+    pub fn despawn_enemies(&mut self) -> Vec<String> {
+        let mut despawned: Vec<String> = vec![];
+        let (distance, after): (f32, Duration) = (self.director.settings.despawn_distance, self.director.settings.despawn_after);
+        let mut index: usize = 0;
+        while index < self.enemies.len() {
+            let enemy: &mut Enemy = &mut self.enemies[index];
+            let boss: bool = self.encounter.as_ref().is_some_and(|encounter| encounter.boss == Some(enemy.uuid));
+            let far: bool = !boss && self.players.iter().all(|player| (&player.position - &enemy.position).magnitude > distance);
+            if !far {
+                enemy.far_since = None;
+            } else if enemy.far_since.get_or_insert_with(Instant::now).elapsed() >= after {
+                let uuid: usize = enemy.uuid;
+                self.remove_enemy(index);
+                despawned.push(uuid.to_string());
+                continue;
+            }
+            index += 1;
+        }
+        despawned
+    }
+    fn remove_enemy(&mut self, index: usize) {
+        let uuid: usize = self.enemies.remove(index).uuid;
+        self.pending.remove(&uuid);
+        self.paths.cancel(uuid);
+        self.packets.push(format!("<edis>{uuid}!"));
+    }
+    // Applies player attacks that are in range and off cooldown. Returns the uuids of enemies that died,
+    // which have already been removed and announced with <edis>.
+    pub fn resolve_attacks(&mut self, attacks: Vec<(PlayerData, usize)>) -> Vec<String> {
+        let mut dead: Vec<String> = vec![];
+        for (player, uuid) in attacks {
+            let now: Instant = Instant::now();
+            if self.attack_times.get(&player.username).is_some_and(|time| now.duration_since(*time) < PLAYER_ATTACK_COOLDOWN) { continue; }
+            let Some(index) = self.enemies.iter().position(|enemy| enemy.uuid == uuid) else { continue; };
+            if (&self.enemies[index].position - &player.position).magnitude > PLAYER_ATTACK_RANGE { continue; }
+            self.attack_times.insert(player.username.clone(), now);
+            let enemy: &mut Enemy = &mut self.enemies[index];
+            enemy.health -= PLAYER_DAMAGE;
+            // Getting hit gives the attacker away.
+            enemy.memory = Some(Memory { username: player.username.clone(), position: player.position.clone(), time: now });
+            self.packets.push(format!("<eh>{},{},{}!", uuid, enemy.health.max(0), player.username));
+            if enemy.health <= 0 {
+                self.remove_enemy(index);
+                dead.push(uuid.to_string());
+            }
+        }
+        dead
+    }
     pub fn move_enemies(&mut self, deltatime: f32) {
+        // Firing first means enemies that can shoot do so rather than striking up close.
+        self.fire_projectiles();
         for enemy in self.enemies.iter_mut() {
-            self.packets.append(&mut enemy.movement(deltatime, &self.players));
+            self.packets.append(&mut enemy.movement(deltatime, &self.players, &self.tilemap));
+        }
+        self.update_projectiles(deltatime);
+    }
+    // Ranged enemies told to fire shoot at their target whenever it is in range and off cooldown.
+    fn fire_projectiles(&mut self) {
+        for enemy in self.enemies.iter_mut() {
+            let Some(ranged) = enemy.ranged else { continue; };
+            if !enemy.fires || enemy.last_hit.elapsed() < enemy.cooldown { continue; }
+            let Some(player) = enemy.target.as_ref().and_then(|username| self.players.iter().find(|player| &player.username == username)) else { continue; };
+            if !has_shot(&self.tilemap, &enemy.position, &player.position, ranged.range) { continue; }
+            let aim: f32 = (&player.position - &enemy.position).direction;
+            let lifetime: Duration = Duration::from_secs_f32(ranged.range * PROJECTILE_LIFETIME / ranged.speed.max(0.01f32));
+            // A full circle has no edge to leave a gap at, so its last projectile would double up on the first.
+            let gaps: usize = if ranged.spread >= TAU { ranged.count } else { ranged.count.saturating_sub(1) };
+            for shot in 0..ranged.count {
+                let offset: f32 = if gaps == 0 { 0f32 } else { ranged.spread * shot as f32 / gaps as f32 - ranged.spread / 2f32 };
+                self.projectile_uuid += 1;
+                let projectile: Projectile = Projectile::new(self.projectile_uuid, enemy.position.clone(), Vector::polar(aim + offset, ranged.speed), enemy.damage, lifetime);
+                self.packets.push(projectile.spawn_packet(enemy.uuid));
+                self.projectiles.push(projectile);
+            }
+            enemy.last_hit = Instant::now();
+        }
+    }
+    fn update_projectiles(&mut self, deltatime: f32) {
+        let mut index: usize = 0;
+        while index < self.projectiles.len() {
+            let projectile: &mut Projectile = &mut self.projectiles[index];
+            let Some(impact) = projectile.update(deltatime, &self.tilemap, &self.players) else {
+                self.packets.push(format!("<pru>{},{},{}!", projectile.uuid, projectile.position.x, projectile.position.y));
+                index += 1;
+                continue;
+            };
+            let hit: String = match impact {
+                Impact::Player(username) => {
+                    self.packets.push(format!("<ph>{},{}!", username, -projectile.damage));
+                    username
+                }
+                Impact::Tile => "tile".to_string(),
+                Impact::Expired => "NONE".to_string()
+            };
+            self.packets.push(format!("<pri>{},{},{},{hit}!", projectile.uuid, projectile.position.x, projectile.position.y));
+            self.projectiles.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::director::DirectorSettings;
+
+    // Two rooms with a solid wall between them, drawn top row first.
+    const ROOMS: [&str; 8] = [
+        "################",
+        "#......#.......#",
+        "#......#.......#",
+        "#......#.......#",
+        "#......#.......#",
+        "#......#.......#",
+        "#......#.......#",
+        "################"
+    ];
+
+    fn controller() -> Controller {
+        let rows: Vec<Vec<usize>> = ROOMS.iter().rev().map(|row| row.chars().map(|tile| if tile == '#' { 1 } else { 2 }).collect()).collect();
+        Controller::new(vec![], &Tilemap::new(rows), vec![], Director::new(DirectorSettings::default(), 0, Instant::now()), JumpSettings::default(), None)
+    }
+
+    fn archetype(ai: Ai, behaviour: Behaviour) -> Archetype {
+        Archetype {
+            name: String::from("test"),
+            sprite: 0,
+            ai,
+            behaviour,
+            max_speed: 3f32,
+            acceleration: 20f32,
+            ranged: None,
+            health: 40,
+            damage: 10,
+            cooldown: Duration::from_millis(1000),
+            senses: Senses { sight: 12f32, ..Senses::default() },
+            weight: 1,
+            tree: None
         }
     }
+
+    fn add_enemy(controller: &mut Controller, archetype: &Archetype, position: Vector) {
+        controller.enemies.push(Enemy::spawn(&mut controller.uuid, archetype, position, 0, 1f32));
+    }
+
+    fn player(username: &str, x: f32, y: f32) -> PlayerData {
+        let mut player: PlayerData = PlayerData::new();
+        player.position = Vector::component(x, y);
+        player.username = String::from(username);
+        player
+    }
+
+    #[test]
+    fn hunter_keeps_quarry_out_of_sight() {
+        let mut controller: Controller = controller();
+        add_enemy(&mut controller, &archetype(Ai::Ground, Behaviour::SeekFromAnywhere), Vector::component(2f32, 2f32));
+        controller.update_players(vec![player("alice", 4f32, 2f32), player("bob", 12f32, 2f32)]);
+        assert_eq!(controller.choose_target(0).map(|(username, _)| username), Some(String::from("alice")));
+
+        // Alice slips into the other room and Bob walks right up to the hunter.
+        controller.update_players(vec![player("alice", 12f32, 5f32), player("bob", 3f32, 2f32)]);
+        assert!(!line_of_sight(&controller.tilemap, &controller.enemies[0].position, &Vector::component(12f32, 5f32)));
+        let (username, position): (String, Vector) = controller.choose_target(0).unwrap();
+        assert_eq!(username, "alice");
+        assert_eq!((position.x, position.y), (12f32, 5f32));
+    }
+
+    #[test]
+    fn attack_moving_ignores_still_players() {
+        let mut controller: Controller = controller();
+        add_enemy(&mut controller, &archetype(Ai::Spider, Behaviour::AttackMoving), Vector::component(2f32, 2f32));
+        controller.update_players(vec![player("still", 3f32, 2f32), player("mover", 5f32, 2f32)]);
+        assert!(controller.choose_target(0).is_none());
+
+        // The still player last moved long enough ago to be forgotten, the other has only just moved.
+        controller.update_players(vec![player("still", 3f32, 2f32), player("mover", 5.5f32, 2f32)]);
+        controller.last_moved.insert(String::from("still"), Instant::now() - MOVING_WINDOW * 2);
+        assert_eq!(controller.choose_target(0).map(|(username, _)| username), Some(String::from("mover")));
+    }
 }
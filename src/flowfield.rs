@@ -0,0 +1,90 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use crate::astar::{walkable, Ai, MovementCosts, Position};
+use crate::vector::Vector;
+
+// Cost to reach one target from every tile, for one kind of Ai. Built with a single backwards
+// Dijkstra from the target, so any number of enemies can then walk downhill to it.
+pub struct FlowField {
+    width: usize,
+    height: usize,
+    distances: Vec<usize>
+}
+
+// Tiles that can step onto position. Movement is not symmetric (ground enemies drop but do not
+// jump) so these are found by asking each surrounding tile where it can go.
+fn predecessors(tilemap: &[Vec<usize>], position: &Position, ai: &Ai) -> Vec<Position> {
+    let mut found: Vec<Position> = vec![];
+    for y in position.y.saturating_sub(1)..=(position.y + 1).min(tilemap.len() - 1) {
+        for x in position.x.saturating_sub(1)..=(position.x + 1).min(tilemap[y].len() - 1) {
+            if x == position.x && y == position.y { continue; }
+            let other: Position = Position::new(x, y);
+            if other.get_adjacent(tilemap, ai).contains(position) && walkable(tilemap, &other, position, ai) {
+                found.push(other);
+            }
+        }
+    }
+    found
+}
+
+impl FlowField {
+    pub fn new(tilemap: &[Vec<usize>], target: Position, ai: &Ai) -> Self {
+        let costs: MovementCosts = ai.costs();
+        let height: usize = tilemap.len();
+        let width: usize = tilemap.first().map_or(0, |row| row.len());
+        let mut distances: Vec<usize> = vec![usize::MAX; width * height];
+        if target.x >= width || target.y >= height {
+            return FlowField { width, height, distances };
+        }
+        let mut open: BinaryHeap<Reverse<(usize, usize)>> = BinaryHeap::new();
+        distances[target.y * width + target.x] = 0;
+        open.push(Reverse((0, target.y * width + target.x)));
+        while let Some(Reverse((distance, index))) = open.pop() {
+            if distance > distances[index] { continue; }
+            let position: Position = Position::new(index % width, index / width);
+            for other in predecessors(tilemap, &position, ai) {
+                let other_index: usize = other.y * width + other.x;
+                let cost: usize = distance + costs.step(tilemap, &other, &position);
+                if cost < distances[other_index] {
+                    distances[other_index] = cost;
+                    open.push(Reverse((cost, other_index)));
+                }
+            }
+        }
+        FlowField { width, height, distances }
+    }
+
+    pub fn distance(&self, position: &Position) -> Option<usize> {
+        if position.x >= self.width || position.y >= self.height {
+            return None;
+        }
+        match self.distances[position.y * self.width + position.x] {
+            usize::MAX => None,
+            distance => Some(distance)
+        }
+    }
+
+    // Follows the field downhill from start, giving the same kind of path astar() returns.
+    pub fn path(&self, tilemap: &[Vec<usize>], start: Position, ai: &Ai) -> Option<Vec<Vector>> {
+        let costs: MovementCosts = ai.costs();
+        let mut distance: usize = self.distance(&start)?;
+        let mut current: Position = start;
+        let mut path: Vec<Vector> = vec![Vector::component(current.x as f32, current.y as f32)];
+        while distance > 0 {
+            let mut best: Option<(usize, Position)> = None;
+            for adjacent in current.get_adjacent(tilemap, ai) {
+                if !walkable(tilemap, &current, &adjacent, ai) { continue; }
+                let Some(remaining) = self.distance(&adjacent) else { continue; };
+                if remaining + costs.step(tilemap, &current, &adjacent) != distance { continue; }
+                if best.as_ref().is_none_or(|best| remaining < best.0) {
+                    best = Some((remaining, adjacent));
+                }
+            }
+            let (remaining, next) = best?;
+            path.push(Vector::component(next.x as f32, next.y as f32));
+            distance = remaining;
+            current = next;
+        }
+        Some(path)
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+// Close enough to strike, and how far the player can then get before the enemy gives chase again.
+pub const ATTACK_RANGE: f32 = 0.8f32;
+const ATTACK_LEASH: f32 = 1.2f32;
+// Enemies at or below this fraction of their health run from anyone they know about.
+const FLEE_HEALTH: f32 = 0.35f32;
+const IDLE_TIME: Duration = Duration::from_millis(3000);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum EnemyState {
+    Idle,
+    Patrol,
+    Investigate,
+    Chase,
+    Attack,
+    Flee,
+    Return
+}
+
+// What the enemy knows about its target this update.
+pub enum Awareness {
+    Nothing,
+    Remembered,
+    // Perceived right now, at this distance.
+    Perceived(f32)
+}
+
+impl EnemyState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EnemyState::Idle => "idle",
+            EnemyState::Patrol => "patrol",
+            EnemyState::Investigate => "investigate",
+            EnemyState::Chase => "chase",
+            EnemyState::Attack => "attack",
+            EnemyState::Flee => "flee",
+            EnemyState::Return => "return"
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<EnemyState> {
+        match name {
+            "idle" => Some(EnemyState::Idle),
+            "patrol" => Some(EnemyState::Patrol),
+            "investigate" => Some(EnemyState::Investigate),
+            "chase" => Some(EnemyState::Chase),
+            "attack" => Some(EnemyState::Attack),
+            "flee" => Some(EnemyState::Flee),
+            "return" => Some(EnemyState::Return),
+            _ => None
+        }
+    }
+
+    // `arrived` is whether the enemy has finished moving towards the goal of its current state.
+    pub fn next(self, awareness: &Awareness, health: f32, since: Instant, arrived: bool) -> EnemyState {
+        let aware: bool = !matches!(awareness, Awareness::Nothing);
+        if aware && health <= FLEE_HEALTH {
+            return EnemyState::Flee;
+        }
+        match (awareness, self) {
+            (Awareness::Perceived(distance), _) if *distance <= ATTACK_RANGE => EnemyState::Attack,
+            (Awareness::Perceived(distance), EnemyState::Attack) if *distance <= ATTACK_LEASH => EnemyState::Attack,
+            (Awareness::Perceived(_), _) => EnemyState::Chase,
+            (Awareness::Remembered, _) => EnemyState::Investigate,
+            (Awareness::Nothing, EnemyState::Chase | EnemyState::Attack | EnemyState::Investigate | EnemyState::Flee) => EnemyState::Return,
+            (Awareness::Nothing, EnemyState::Return | EnemyState::Patrol) if arrived => EnemyState::Idle,
+            (Awareness::Nothing, EnemyState::Idle) if since.elapsed() >= IDLE_TIME => EnemyState::Patrol,
+            (Awareness::Nothing, state) => state
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use clap::ValueEnum;
+use rand::{Rng, thread_rng};
+use rand::rngs::ThreadRng;
+use rand::seq::SliceRandom;
+use crate::astar::{Position, is_solid};
+use crate::tilemap::{Tilemap, RoomArea, randomize_rooms, decorate};
+
+pub trait Generator {
+    fn generate(&self, depth: usize) -> Tilemap;
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum GeneratorKind {
+    Rooms,
+    Caves,
+    Bsp
+}
+
+impl GeneratorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorKind::Rooms => "rooms",
+            GeneratorKind::Caves => "caves",
+            GeneratorKind::Bsp => "bsp"
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Generator> {
+        match self {
+            GeneratorKind::Rooms => Box::new(RoomGenerator { stop: 6 }),
+            GeneratorKind::Caves => Box::new(CaveGenerator { fill: 0.5f64, iterations: 5, min_region: 24 }),
+            GeneratorKind::Bsp => Box::new(BspGenerator { min_leaf: 14 })
+        }
+    }
+}
+
+// Stitches the hand-made rooms in assets/levels/ together.
+pub struct RoomGenerator {
+    pub stop: usize
+}
+
+impl Generator for RoomGenerator {
+    fn generate(&self, depth: usize) -> Tilemap {
+        Tilemap::from(randomize_rooms(depth + 1, self.stop))
+    }
+}
+
+// Cellular automata caves. Every open region big enough to keep becomes a room and is tunnelled to
+// the closest region that is already connected.
+pub struct CaveGenerator {
+    pub fill: f64,
+    pub iterations: usize,
+    pub min_region: usize
+}
+
+pub struct BspGenerator {
+    pub min_leaf: usize
+}
+
+fn map_size(depth: usize) -> usize {
+    (depth + 2) * 32
+}
+
+fn solid_neighbours(grid: &[Vec<usize>], x: usize, y: usize) -> usize {
+    let mut count: usize = 0;
+    for (ny, row) in grid.iter().enumerate().skip(y - 1).take(3) {
+        for (nx, tile) in row.iter().enumerate().skip(x - 1).take(3) {
+            if (nx != x || ny != y) && is_solid(*tile) {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn regions(grid: &[Vec<usize>]) -> Vec<Vec<[usize; 2]>> {
+    let size: usize = grid.len();
+    let mut seen: Vec<Vec<bool>> = vec![vec![false; size]; size];
+    let mut regions: Vec<Vec<[usize; 2]>> = vec![];
+    for y in 0..size {
+        for x in 0..size {
+            if seen[y][x] || is_solid(grid[y][x]) { continue; }
+            let mut region: Vec<[usize; 2]> = vec![];
+            let mut stack: Vec<[usize; 2]> = vec![[x, y]];
+            seen[y][x] = true;
+            while let Some([cx, cy]) = stack.pop() {
+                region.push([cx, cy]);
+                for [nx, ny] in [[cx - 1, cy], [cx + 1, cy], [cx, cy - 1], [cx, cy + 1]] {
+                    if !seen[ny][nx] && !is_solid(grid[ny][nx]) {
+                        seen[ny][nx] = true;
+                        stack.push([nx, ny]);
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+fn bounding_area(region: &[[usize; 2]]) -> RoomArea {
+    let min_x: usize = region.iter().map(|tile| tile[0]).min().unwrap_or(0);
+    let max_x: usize = region.iter().map(|tile| tile[0]).max().unwrap_or(0);
+    let min_y: usize = region.iter().map(|tile| tile[1]).min().unwrap_or(0);
+    let max_y: usize = region.iter().map(|tile| tile[1]).max().unwrap_or(0);
+    RoomArea { x: min_x, y: min_y, width: max_x - min_x + 1, height: max_y - min_y + 1 }
+}
+
+// Shared by the generated layouts: a spawn point per room, decorations on the floors and the player
+// spawn in the chosen spawn room.
+fn finish(tilemap: &mut Tilemap, fallback: &[[usize; 2]], rng: &mut ThreadRng) {
+    for (room, fallback) in fallback.iter().enumerate() {
+        let tiles: Vec<[usize; 2]> = tilemap.standable_tiles(room);
+        let location: [usize; 2] = match tiles.choose(rng) {
+            Some(tile) => *tile,
+            None => *fallback
+        };
+        tilemap.spawn_locations.push(location);
+        for tile in tiles {
+            if tile != location {
+                decorate(&mut tilemap.tilemap[tile[1]][tile[0]], rng);
+            }
+        }
+    }
+    tilemap.spawn_coordinates = tilemap.spawn_locations[tilemap.spawn_room];
+    println!("Chose spawn location: [{},{}]!", tilemap.spawn_coordinates[0], tilemap.spawn_coordinates[1]);
+}
+
+fn corridor_between(tilemap: &mut Tilemap, start: [usize; 2], end: [usize; 2], rooms: [usize; 2], rng: &mut ThreadRng) {
+    let vertical: bool = start[1].abs_diff(end[1]) > start[0].abs_diff(end[0]);
+    tilemap.carve_corridor(Position::from(start), Position::from(end), rooms, vertical, rng);
+}
+
+impl Generator for CaveGenerator {
+    fn generate(&self, depth: usize) -> Tilemap {
+        let mut rng: ThreadRng = thread_rng();
+        let size: usize = map_size(depth);
+        let mut grid: Vec<Vec<usize>> = vec![vec![4; size]; size];
+        for row in grid.iter_mut().take(size - 1).skip(1) {
+            for tile in row.iter_mut().take(size - 1).skip(1) {
+                if !rng.gen_bool(self.fill) {
+                    *tile = 2;
+                }
+            }
+        }
+        for _ in 0..self.iterations {
+            let mut next: Vec<Vec<usize>> = grid.clone();
+            for (y, row) in next.iter_mut().enumerate().take(size - 1).skip(1) {
+                for (x, tile) in row.iter_mut().enumerate().take(size - 1).skip(1) {
+                    let solid: usize = solid_neighbours(&grid, x, y);
+                    if solid > 4 {
+                        *tile = 4;
+                    } else if solid < 4 {
+                        *tile = 2;
+                    }
+                }
+            }
+            grid = next;
+        }
+
+        let mut kept: Vec<Vec<[usize; 2]>> = vec![];
+        for region in regions(&grid) {
+            if region.len() >= self.min_region {
+                kept.push(region);
+            } else {
+                for [x, y] in region {
+                    grid[y][x] = 4;
+                }
+            }
+        }
+
+        let mut tilemap: Tilemap = Tilemap::new(grid);
+        for region in &kept {
+            tilemap.rooms.push(bounding_area(region));
+        }
+        for room in 1..kept.len() {
+            let mut best: Option<([usize; 2], [usize; 2], usize, usize)> = None;
+            for (other, region) in kept.iter().enumerate().take(room) {
+                for a in kept[room].iter().step_by(3) {
+                    for b in region.iter().step_by(3) {
+                        let distance: usize = a[0].abs_diff(b[0]) + a[1].abs_diff(b[1]);
+                        if best.is_none_or(|best| distance < best.3) {
+                            best = Some((*a, *b, other, distance));
+                        }
+                    }
+                }
+            }
+            if let Some((start, end, other, _)) = best {
+                corridor_between(&mut tilemap, start, end, [room, other], &mut rng);
+            }
+        }
+        let fallback: Vec<[usize; 2]> = kept.iter().map(|region| region[0]).collect();
+        if !fallback.is_empty() {
+            finish(&mut tilemap, &fallback, &mut rng);
+        }
+        tilemap
+    }
+}
+
+impl BspGenerator {
+    // Splits the area until the leaves are small, places a room in each leaf and joins the two halves
+    // of every split with a corridor. Returns the rooms created inside the area.
+    fn split(&self, area: [usize; 4], tilemap: &mut Tilemap, rng: &mut ThreadRng) -> Vec<usize> {
+        let [x, y, width, height] = area;
+        let horizontal: bool = if width >= 2 * self.min_leaf && height >= 2 * self.min_leaf {
+            rng.gen_bool(0.5f64)
+        } else {
+            width >= 2 * self.min_leaf
+        };
+        let (first, second): ([usize; 4], [usize; 4]) = if horizontal {
+            let cut: usize = rng.gen_range(self.min_leaf..=width - self.min_leaf);
+            ([x, y, cut, height], [x + cut, y, width - cut, height])
+        } else if height >= 2 * self.min_leaf {
+            let cut: usize = rng.gen_range(self.min_leaf..=height - self.min_leaf);
+            ([x, y, width, cut], [x, y + cut, width, height - cut])
+        } else {
+            let room_width: usize = rng.gen_range(6..=width - 3);
+            let room_height: usize = rng.gen_range(5..=height - 3);
+            let room_x: usize = x + rng.gen_range(1..=width - room_width - 2);
+            let room_y: usize = y + rng.gen_range(1..=height - room_height - 2);
+            for row in tilemap.tilemap.iter_mut().skip(room_y - 1).take(room_height + 2) {
+                for tile in row.iter_mut().skip(room_x - 1).take(room_width + 2) {
+                    *tile = 4;
+                }
+            }
+            for row in tilemap.tilemap.iter_mut().skip(room_y).take(room_height) {
+                for tile in row.iter_mut().skip(room_x).take(room_width) {
+                    *tile = 2;
+                }
+            }
+            tilemap.rooms.push(RoomArea { x: room_x, y: room_y, width: room_width, height: room_height });
+            return vec![tilemap.rooms.len() - 1];
+        };
+        let mut left: Vec<usize> = self.split(first, tilemap, rng);
+        let mut right: Vec<usize> = self.split(second, tilemap, rng);
+        let mut best: Option<(usize, usize, usize)> = None;
+        for a in &left {
+            for b in &right {
+                let room_a: &RoomArea = &tilemap.rooms[*a];
+                let room_b: &RoomArea = &tilemap.rooms[*b];
+                let distance: usize = (room_a.x + room_a.width / 2).abs_diff(room_b.x + room_b.width / 2) + room_a.y.abs_diff(room_b.y);
+                if best.is_none_or(|best| distance < best.2) {
+                    best = Some((*a, *b, distance));
+                }
+            }
+        }
+        if let Some((a, b, _)) = best {
+            let start: [usize; 2] = [tilemap.rooms[a].x + tilemap.rooms[a].width / 2, tilemap.rooms[a].y];
+            let end: [usize; 2] = [tilemap.rooms[b].x + tilemap.rooms[b].width / 2, tilemap.rooms[b].y];
+            corridor_between(tilemap, start, end, [a, b], rng);
+        }
+        left.append(&mut right);
+        left
+    }
+}
+
+impl Generator for BspGenerator {
+    fn generate(&self, depth: usize) -> Tilemap {
+        let mut rng: ThreadRng = thread_rng();
+        let size: usize = map_size(depth);
+        let mut tilemap: Tilemap = Tilemap::new(vec![vec![1; size]; size]);
+        self.split([1, 1, size - 2, size - 2], &mut tilemap, &mut rng);
+        tilemap.spawn_room = rng.gen_range(0..tilemap.rooms.len());
+        let fallback: Vec<[usize; 2]> = tilemap.rooms.iter().map(|room| [room.x, room.y]).collect();
+        finish(&mut tilemap, &fallback, &mut rng);
+        tilemap
+    }
+}
@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::time::{Instant, Duration};
+use crate::packet::PlayerData;
+
+// One line per hazard tile in the tile data: "<tile id> <name> <damage> <tick ms>". The name is only
+// there for whoever edits the file.
+#[derive(Clone)]
+pub struct Hazard {
+    pub tile: usize,
+    pub damage: i32,
+    pub tick: Duration
+}
+
+pub fn load_hazards(filename: &str) -> Vec<Hazard> {
+    let mut hazards: Vec<Hazard> = vec![];
+    match read_to_string(filename) {
+        Ok(file_string) => {
+            for line in file_string.lines() {
+                let fields: Vec<&str> = line.split(' ').collect();
+                if fields.len() < 4 { continue; }
+                if let (Ok(tile), Ok(damage), Ok(tick)) = (fields[0].parse::<usize>(), fields[2].parse::<i32>(), fields[3].parse::<u64>()) {
+                    hazards.push(Hazard { tile, damage, tick: Duration::from_millis(tick) });
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+    hazards
+}
+
+pub struct HazardTracker {
+    hazards: Vec<Hazard>,
+    tilemap: Vec<Vec<usize>>,
+    // The hazard each player is standing in and when it next hurts them.
+    due: HashMap<String, (usize, Instant)>
+}
+
+impl HazardTracker {
+    pub fn new(hazards: Vec<Hazard>, tilemap: Vec<Vec<usize>>) -> Self {
+        HazardTracker { hazards, tilemap, due: HashMap::new() }
+    }
+
+    fn hazard_at(&self, player: &PlayerData) -> Option<&Hazard> {
+        if player.position.x < 0.0f32 || player.position.y < 0.0f32 {
+            return None;
+        }
+        let x: usize = player.position.x.round() as usize;
+        let y: usize = player.position.y.round() as usize;
+        let tile: usize = *self.tilemap.get(y)?.get(x)?;
+        self.hazards.iter().find(|hazard| hazard.tile == tile)
+    }
+
+    // Damage is dealt on contact and then once per tick for as long as the player stays in the hazard.
+    // Ticks are counted from contact rather than from whenever this runs, so a slow caller is caught up
+    // instead of stretching them.
+    pub fn update(&mut self, players: &[PlayerData]) -> Vec<String> {
+        let mut packets: Vec<String> = vec![];
+        let now: Instant = Instant::now();
+        for player in players {
+            let Some(hazard) = self.hazard_at(player).cloned() else {
+                self.due.remove(&player.username);
+                continue;
+            };
+            let tick: Duration = hazard.tick.max(Duration::from_millis(1));
+            let hits: i32 = match self.due.get_mut(&player.username) {
+                Some((tile, due)) if *tile == hazard.tile => {
+                    let mut hits: i32 = 0;
+                    while *due <= now {
+                        hits += 1;
+                        *due += tick;
+                    }
+                    hits
+                }
+                _ => {
+                    self.due.insert(player.username.clone(), (hazard.tile, now + tick));
+                    1
+                }
+            };
+            if hits > 0 {
+                packets.push(format!("<ph>{},{}!", player.username, -hazard.damage * hits));
+            }
+        }
+        packets
+    }
+}
@@ -0,0 +1,175 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::astar::{astar, search, Ai, MovementCosts, Position};
+use crate::tilemap::{RoomArea, Tilemap};
+use crate::vector::Vector;
+
+type Segment = Option<(Vec<Vector>, usize)>;
+
+// Where a corridor meets a room.
+#[derive(Clone)]
+pub struct Entrance {
+    pub room: usize,
+    pub position: Position,
+    pub corridor_end: usize
+}
+
+// The coarse level: rooms, with an entrance at each end of every corridor.
+#[derive(Clone, Default)]
+pub struct RoomGraph {
+    rooms: Vec<RoomArea>,
+    width: usize,
+    room_lookup: Vec<usize>,
+    entrances: Vec<Entrance>
+}
+
+impl From<&Tilemap> for RoomGraph {
+    fn from(tilemap: &Tilemap) -> Self {
+        let height: usize = tilemap.tilemap.len();
+        let width: usize = tilemap.tilemap.first().map_or(0, |row| row.len());
+        let mut room_lookup: Vec<usize> = vec![usize::MAX; width * height];
+        for (index, room) in tilemap.rooms.iter().enumerate() {
+            for y in room.y..(room.y + room.height).min(height) {
+                for x in room.x..(room.x + room.width).min(width) {
+                    if room_lookup[y * width + x] == usize::MAX {
+                        room_lookup[y * width + x] = index;
+                    }
+                }
+            }
+        }
+        let mut entrances: Vec<Entrance> = vec![];
+        for connection in &tilemap.connections {
+            if let (Some(first), Some(last)) = (connection.path.first(), connection.path.last()) {
+                let index: usize = entrances.len();
+                entrances.push(Entrance { room: connection.rooms[0], position: first.clone(), corridor_end: index + 1 });
+                entrances.push(Entrance { room: connection.rooms[1], position: last.clone(), corridor_end: index });
+            }
+        }
+        RoomGraph { rooms: tilemap.rooms.clone(), width, room_lookup, entrances }
+    }
+}
+
+impl RoomGraph {
+    pub fn room_of(&self, position: &Position) -> Option<usize> {
+        if position.x >= self.width {
+            return None;
+        }
+        match self.room_lookup.get(position.y * self.width + position.x) {
+            Some(room) if *room != usize::MAX => Some(*room),
+            _ => None
+        }
+    }
+}
+
+// Paths between two rooms are planned over the entrances, using cached entrance-to-entrance paths.
+// Only the legs from the start and to the goal are searched fresh, and only inside their own rooms.
+pub struct HierarchicalPathfinder {
+    graph: RoomGraph,
+    cache: HashMap<(Ai, usize, usize), Segment>
+}
+
+impl HierarchicalPathfinder {
+    pub fn new(graph: RoomGraph) -> Self {
+        HierarchicalPathfinder { graph, cache: HashMap::new() }
+    }
+
+    // Cached segments go stale once a tile on the map changes.
+    pub fn clear(&mut self) {
+        self.cache.clear();
+    }
+
+    fn segment(&mut self, tilemap: &[Vec<usize>], ai: &Ai, from: usize, to: usize) -> Segment {
+        if let Some(segment) = self.cache.get(&(*ai, from, to)) {
+            return segment.clone();
+        }
+        let start: &Entrance = &self.graph.entrances[from];
+        let end: &Entrance = &self.graph.entrances[to];
+        let area: Option<&RoomArea> = if start.corridor_end == to { None } else { Some(&self.graph.rooms[start.room]) };
+        let segment: Segment = search(tilemap, start.position.clone(), end.position.clone(), ai, &ai.costs(), area);
+        self.cache.insert((*ai, from, to), segment.clone());
+        segment
+    }
+
+    pub fn find_path(&mut self, tilemap: &[Vec<usize>], start: Position, end: Position, ai: &Ai) -> Option<Vec<Vector>> {
+        let (Some(start_room), Some(end_room)) = (self.graph.room_of(&start), self.graph.room_of(&end)) else {
+            return astar(tilemap, start, end, ai);
+        };
+        if start_room == end_room {
+            return astar(tilemap, start, end, ai);
+        }
+        match self.hierarchical_path(tilemap, &start, &end, start_room, end_room, ai) {
+            Some(path) => Some(path),
+            None => astar(tilemap, start, end, ai)
+        }
+    }
+
+    fn hierarchical_path(&mut self, tilemap: &[Vec<usize>], start: &Position, end: &Position, start_room: usize, end_room: usize, ai: &Ai) -> Option<Vec<Vector>> {
+        let costs: MovementCosts = ai.costs();
+        let count: usize = self.graph.entrances.len();
+        let goal: usize = count;
+        let mut first_legs: HashMap<usize, Vec<Vector>> = HashMap::new();
+        let mut last_legs: HashMap<usize, (Vec<Vector>, usize)> = HashMap::new();
+        let mut distances: Vec<usize> = vec![usize::MAX; count + 1];
+        let mut previous: Vec<usize> = vec![usize::MAX; count + 1];
+        let mut open: BinaryHeap<Reverse<(usize, usize)>> = BinaryHeap::new();
+
+        for (index, entrance) in self.graph.entrances.iter().enumerate() {
+            if entrance.room == start_room {
+                if let Some((path, cost)) = search(tilemap, start.clone(), entrance.position.clone(), ai, &costs, Some(&self.graph.rooms[start_room])) {
+                    distances[index] = cost;
+                    first_legs.insert(index, path);
+                    open.push(Reverse((cost, index)));
+                }
+            }
+            if entrance.room == end_room {
+                if let Some(leg) = search(tilemap, entrance.position.clone(), end.clone(), ai, &costs, Some(&self.graph.rooms[end_room])) {
+                    last_legs.insert(index, leg);
+                }
+            }
+        }
+
+        while let Some(Reverse((distance, node))) = open.pop() {
+            if node == goal { break; }
+            if distance > distances[node] { continue; }
+            let mut edges: Vec<(usize, usize)> = vec![];
+            if let Some((_, cost)) = last_legs.get(&node) {
+                edges.push((goal, *cost));
+            }
+            let room: usize = self.graph.entrances[node].room;
+            let corridor_end: usize = self.graph.entrances[node].corridor_end;
+            let mut neighbours: Vec<usize> = vec![corridor_end];
+            neighbours.extend((0..count).filter(|other| *other != node && self.graph.entrances[*other].room == room));
+            for other in neighbours {
+                if let Some((_, cost)) = self.segment(tilemap, ai, node, other) {
+                    edges.push((other, cost));
+                }
+            }
+            for (other, cost) in edges {
+                if distance + cost < distances[other] {
+                    distances[other] = distance + cost;
+                    previous[other] = node;
+                    open.push(Reverse((distance + cost, other)));
+                }
+            }
+        }
+        if distances[goal] == usize::MAX {
+            return None;
+        }
+
+        let mut nodes: Vec<usize> = vec![];
+        let mut node: usize = previous[goal];
+        while node != usize::MAX {
+            nodes.push(node);
+            node = previous[node];
+        }
+        nodes.reverse();
+        let mut path: Vec<Vector> = first_legs.remove(&nodes[0])?;
+        for pair in nodes.windows(2) {
+            let (segment, _) = self.segment(tilemap, ai, pair[0], pair[1])?;
+            path.extend(segment.into_iter().skip(1));
+        }
+        let (last_leg, _) = last_legs.remove(nodes.last()?)?;
+        path.extend(last_leg.into_iter().skip(1));
+        Some(path)
+    }
+}
@@ -0,0 +1,24 @@
+pub mod network;
+mod packet;
+pub mod tilemap;
+mod archetype;
+pub mod astar;
+mod boss;
+mod btree;
+pub mod debug;
+mod director;
+mod enemy;
+pub mod flowfield;
+mod fsm;
+pub mod generator;
+mod hazard;
+mod hierarchy;
+pub mod navgraph;
+mod pathservice;
+mod perception;
+mod progression;
+mod projectile;
+mod smoothing;
+mod transfer;
+mod util;
+pub mod vector;
@@ -1,20 +1,39 @@
-mod network;
-mod packet;
-mod tilemap;
-mod astar;
-mod enemy;
-mod util;
-mod vector;
 
-use crate::network::Server;
-use crate::tilemap::{Tilemap, randomize_rooms};
+use abyssal_zone_server::debug;
+use abyssal_zone_server::generator::{Generator, GeneratorKind};
+use abyssal_zone_server::network::Server;
+use abyssal_zone_server::tilemap::{Tilemap, generate_floor};
+use clap::Parser;
 use std::{thread, time::Duration};
 
+#[derive(Parser)]
+struct Args {
+    #[arg(long, value_enum, default_value_t = GeneratorKind::Rooms)]
+    generator: GeneratorKind,
+    #[arg(long, default_value_t = 3)]
+    floors: usize,
+    /// Write the enemy AI debug stream to this file.
+    #[arg(long)]
+    debug_log: Option<String>,
+    /// Draw the last frame of a debug log with every enemy path overlaid and exit.
+    #[arg(long)]
+    render: Option<String>,
+    /// Which floor of the log --render draws.
+    #[arg(long, default_value_t = 0)]
+    render_floor: usize
+}
+
 fn main() {
+    let args: Args = Args::parse();
+    if let Some(filename) = &args.render {
+        debug::render(filename, args.render_floor);
+        return;
+    }
     let duration: Duration = Duration::from_millis(1000);
     println!("Beginning...");
-    let tilemap: Tilemap = Tilemap::from(randomize_rooms(1, 6));
-    match Server::new(tilemap) {
+    let generator: Box<dyn Generator> = args.generator.build();
+    let floors: Vec<Tilemap> = (0..args.floors.max(1)).map(|depth| generate_floor(generator.as_ref(), depth, args.floors)).collect();
+    match Server::new(floors, args.debug_log) {
         Ok(server) => {
             {
                 println!("LAN JOINCODE: {}", server.lock().unwrap().get_joincode());
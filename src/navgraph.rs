@@ -0,0 +1,260 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::read_to_string;
+use crate::astar::{is_solid, Position};
+use crate::vector::Vector;
+
+pub const LADDER: usize = 6;
+const WALK_COST: usize = 10;
+const STEP_UP_COST: usize = 25;
+const STEP_DOWN_COST: usize = 18;
+const CLIMB_COST: usize = 15;
+const FALL_COST: usize = 8;
+const TAKEOFF_COST: usize = 10;
+// No edge costs less than this per tile it covers, which keeps the heuristic admissible.
+const MIN_TILE_COST: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum EdgeKind {
+    Walk,
+    Jump,
+    Fall,
+    Climb
+}
+
+// How far a ground enemy can jump, in tiles. The navigation data sets them with "jump_height <tiles>"
+// and "jump_distance <tiles>" lines.
+#[derive(Clone, Copy)]
+pub struct JumpSettings {
+    pub height: usize,
+    pub distance: usize
+}
+
+impl Default for JumpSettings {
+    fn default() -> Self {
+        JumpSettings { height: 3, distance: 4 }
+    }
+}
+
+fn apply_setting(settings: &mut JumpSettings, fields: &[&str]) -> Option<()> {
+    let value: &str = fields.get(1)?;
+    match fields[0] {
+        "jump_height" => settings.height = value.parse::<usize>().ok()?,
+        "jump_distance" => settings.distance = value.parse::<usize>().ok()?,
+        _ => return None
+    }
+    Some(())
+}
+
+pub fn load_jump_settings(filename: &str) -> JumpSettings {
+    let mut settings: JumpSettings = JumpSettings::default();
+    match read_to_string(filename) {
+        Ok(file_string) => {
+            for line in file_string.lines() {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                if fields.is_empty() { continue; }
+                if apply_setting(&mut settings, &fields).is_none() {
+                    eprintln!("ERROR! Invalid navigation setting: {line}");
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("ERROR! {}", e);
+        }
+    }
+    settings
+}
+
+struct Edge {
+    to: usize,
+    kind: EdgeKind,
+    cost: usize
+}
+
+// Every tile a ground enemy can stand or hang on, and the moves between them. Built once per floor.
+pub struct NavGraph {
+    width: usize,
+    height: usize,
+    nodes: Vec<usize>,
+    positions: Vec<Position>,
+    edges: Vec<Vec<Edge>>
+}
+
+fn tile(tilemap: &[Vec<usize>], x: usize, y: usize) -> usize {
+    tilemap.get(y).and_then(|row| row.get(x)).copied().unwrap_or(1)
+}
+
+fn solid(tilemap: &[Vec<usize>], x: usize, y: usize) -> bool {
+    is_solid(tile(tilemap, x, y))
+}
+
+pub fn standable(tilemap: &[Vec<usize>], x: usize, y: usize) -> bool {
+    if y == 0 || solid(tilemap, x, y) {
+        return false;
+    }
+    tile(tilemap, x, y) == LADDER || solid(tilemap, x, y - 1) || tile(tilemap, x, y - 1) == LADDER
+}
+
+impl NavGraph {
+    pub fn new(tilemap: &[Vec<usize>], jump: JumpSettings) -> Self {
+        let height: usize = tilemap.len();
+        let width: usize = tilemap.first().map_or(0, |row| row.len());
+        let mut nodes: Vec<usize> = vec![usize::MAX; width * height];
+        let mut positions: Vec<Position> = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                if standable(tilemap, x, y) {
+                    nodes[y * width + x] = positions.len();
+                    positions.push(Position::new(x, y));
+                }
+            }
+        }
+        let mut graph: NavGraph = NavGraph { width, height, nodes, positions, edges: vec![] };
+        for node in 0..graph.positions.len() {
+            let position: Position = graph.positions[node].clone();
+            let edges: Vec<Edge> = graph.edges_from(tilemap, &position, jump);
+            graph.edges.push(edges);
+        }
+        graph
+    }
+
+    fn node(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        match self.nodes[y * self.width + x] {
+            usize::MAX => None,
+            node => Some(node)
+        }
+    }
+
+    fn edges_from(&self, tilemap: &[Vec<usize>], position: &Position, jump: JumpSettings) -> Vec<Edge> {
+        let mut edges: Vec<Edge> = vec![];
+        let (x, y) = (position.x, position.y);
+        let mut push = |target: Option<usize>, kind: EdgeKind, cost: usize| {
+            if let Some(to) = target {
+                edges.push(Edge { to, kind, cost });
+            }
+        };
+
+        for nx in [x.wrapping_sub(1), x + 1] {
+            if nx >= self.width { continue; }
+            if standable(tilemap, nx, y) {
+                push(self.node(nx, y), EdgeKind::Walk, WALK_COST);
+            } else if solid(tilemap, nx, y) {
+                if !solid(tilemap, x, y + 1) {
+                    push(self.node(nx, y + 1), EdgeKind::Walk, STEP_UP_COST);
+                }
+            } else if standable(tilemap, nx, y - 1) {
+                push(self.node(nx, y - 1), EdgeKind::Walk, STEP_DOWN_COST);
+            } else {
+                // Walking off a ledge: drop straight down until something is underneath.
+                let mut landing: usize = y - 1;
+                while landing > 0 && !solid(tilemap, nx, landing) {
+                    if standable(tilemap, nx, landing) {
+                        push(self.node(nx, landing), EdgeKind::Fall, WALK_COST + FALL_COST * (y - landing));
+                        break;
+                    }
+                    landing -= 1;
+                }
+            }
+        }
+
+        if tile(tilemap, x, y) == LADDER {
+            push(self.node(x, y + 1), EdgeKind::Climb, CLIMB_COST);
+        }
+        if tile(tilemap, x, y - 1) == LADDER {
+            push(self.node(x, y - 1), EdgeKind::Climb, CLIMB_COST);
+        }
+
+        if !solid(tilemap, x, y - 1) {
+            return edges;
+        }
+        for direction in [-1isize, 1] {
+            for dx in 1..=jump.distance as isize {
+                let tx: isize = x as isize + direction * dx;
+                if tx < 0 || tx as usize >= self.width { break; }
+                let tx: usize = tx as usize;
+                for dy in -(jump.height as isize)..=jump.height as isize {
+                    if dx == 1 && dy.abs() <= 1 { continue; }
+                    let ty: isize = y as isize + dy;
+                    if ty <= 0 || ty as usize >= self.height { continue; }
+                    let ty: usize = ty as usize;
+                    if !solid(tilemap, tx, ty - 1) || self.node(tx, ty).is_none() { continue; }
+                    if !jump_clear(tilemap, [x, y], [tx, ty]) { continue; }
+                    let cost: usize = TAKEOFF_COST + WALK_COST * dx as usize + CLIMB_COST * dy.max(0) as usize + FALL_COST * (-dy).max(0) as usize;
+                    push(self.node(tx, ty), EdgeKind::Jump, cost);
+                }
+            }
+        }
+        edges
+    }
+
+    // Nearest node at or below position, for enemies and players that are mid-air.
+    fn snap(&self, position: &Position) -> Option<usize> {
+        if position.x >= self.width || position.y >= self.height {
+            return None;
+        }
+        (0..=position.y).rev().take(8).find_map(|y| self.node(position.x, y))
+    }
+
+    // Each waypoint comes with the kind of move that reaches it. The first is the start itself.
+    pub fn find_path(&self, start: Position, end: Position) -> Option<Vec<(Vector, EdgeKind)>> {
+        let start: usize = self.snap(&start)?;
+        let end: usize = self.snap(&end)?;
+        let goal: &Position = &self.positions[end];
+        let heuristic = |node: usize| MIN_TILE_COST * (self.positions[node].x.abs_diff(goal.x) + self.positions[node].y.abs_diff(goal.y));
+
+        let mut g_scores: Vec<usize> = vec![usize::MAX; self.positions.len()];
+        let mut parents: Vec<(usize, EdgeKind)> = vec![(usize::MAX, EdgeKind::Walk); self.positions.len()];
+        let mut open: BinaryHeap<Reverse<(usize, usize)>> = BinaryHeap::new();
+        g_scores[start] = 0;
+        open.push(Reverse((heuristic(start), start)));
+        while let Some(Reverse((f, node))) = open.pop() {
+            if node == end {
+                let mut path: Vec<(Vector, EdgeKind)> = vec![];
+                let mut current: usize = node;
+                while current != usize::MAX {
+                    let (parent, kind) = parents[current];
+                    path.push((Vector::from(self.positions[current].clone()), kind));
+                    current = parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if f > g_scores[node] + heuristic(node) { continue; }
+            for edge in &self.edges[node] {
+                let g: usize = g_scores[node] + edge.cost;
+                if g < g_scores[edge.to] {
+                    g_scores[edge.to] = g;
+                    parents[edge.to] = (node, edge.kind);
+                    open.push(Reverse((g + heuristic(edge.to), edge.to)));
+                }
+            }
+        }
+        None
+    }
+}
+
+// The arc needs the tiles from both ends up to one above the higher end free in every column it
+// crosses.
+fn jump_clear(tilemap: &[Vec<usize>], from: [usize; 2], to: [usize; 2]) -> bool {
+    let top: usize = from[1].max(to[1]) + 1;
+    if top >= tilemap.len() {
+        return false;
+    }
+    let (left, right) = (from[0].min(to[0]), from[0].max(to[0]));
+    for x in left..=right {
+        let bottom: usize = if x == from[0] {
+            from[1]
+        } else if x == to[0] {
+            to[1]
+        } else {
+            from[1].max(to[1])
+        };
+        if (bottom..=top).any(|y| solid(tilemap, x, y)) {
+            return false;
+        }
+    }
+    true
+}
@@ -1,14 +1,24 @@
-use std::mem::replace;
+use std::mem::take;
 use std::io::prelude::*;
 use std::net::{SocketAddr, TcpListener, TcpStream};
 use std::sync::{Arc, Mutex};
 use std::thread::{spawn, sleep, JoinHandle};
 use std::time::{Instant, Duration};
 use get_if_addrs::{get_if_addrs, Interface};
-use crate::packet::{PlayerData, tilemap_packet};
-use crate::tilemap::Tilemap;
+use rand::thread_rng;
+use crate::packet::{PlayerData, TilemapChunk, tilemap_chunks, tilemap_header, parse_attack, parse_chunk_ack};
+use crate::tilemap::{apply_changes, Tilemap, TileChange};
 use crate::enemy::Controller;
+use crate::archetype::{load_archetypes, Archetype};
+use crate::boss::{load_bosses, BossDefinition, Encounter};
+use crate::director::{load_director_settings, Director, DirectorSettings};
+use crate::navgraph::{load_jump_settings, JumpSettings};
+use crate::debug::{map_packet, DebugLog};
+use crate::hazard::{Hazard, HazardTracker, load_hazards};
+use crate::progression::Progression;
+use crate::transfer::Transfer;
 use crate::util::split_with_delimiter;
+use crate::vector::Vector;
 
 #[derive(PartialEq)]
 pub enum Status {
@@ -23,8 +33,7 @@ pub enum Error {
 }
 
 struct Listener {
-    client: Option<(TcpStream, SocketAddr)>,
-    initial_packet: Vec<String>
+    clients: Vec<(TcpStream, SocketAddr)>
 }
 
 struct Receiver {
@@ -48,7 +57,31 @@ pub struct Client {
     incoming: Vec<String>,
     outgoing: Vec<String>,
     status: Status,
-    num: isize
+    num: isize,
+    floor: usize,
+    transfer: Option<Transfer>,
+    attacks: Vec<usize>
+}
+
+pub struct Floor {
+    tilemap: Tilemap,
+    tilemap_header: Vec<String>,
+    tilemap_chunks: Vec<TilemapChunk>,
+    spawn: [usize; 2],
+    exit: Option<[usize; 2]>,
+    enemy_controller: Arc<Mutex<Controller>>,
+    controller_thread: Option<JoinHandle<()>>,
+    enemy_thread: Option<JoinHandle<()>>,
+    active_enemies: Vec<String>,
+    enemy_packets: Vec<String>,
+    progression: Progression,
+    hazards: HazardTracker,
+    world_packets: Vec<String>,
+    // Tiles opened by progression that the enemy controller has yet to hear about.
+    enemy_tiles: Vec<TileChange>,
+    // The boss fight as the enemy controller last reported it, for players joining mid-fight.
+    encounter_packets: Vec<String>,
+    debug_packets: Vec<String>
 }
 
 pub struct Server {
@@ -58,29 +91,22 @@ pub struct Server {
     _listen_thread: Option<JoinHandle<()>>,
     running: bool,
     join_code: Option<JoinCode>,
-    enemy_controller: Arc<Mutex<Controller>>,
-    controller_thread: Option<JoinHandle<()>>,
-    enemy_thread: Option<JoinHandle<()>>,
-    active_enemies: Vec<String>,
-    enemy_packets: Vec<String>
+    floors: Vec<Floor>,
+    progression_thread: Option<JoinHandle<()>>,
+    debug_log: Option<DebugLog>
 }
 
 
 fn listen(listener: Arc<Mutex<Listener>>, tcp_listener: TcpListener) {
     loop {
         match tcp_listener.accept() {
-            Ok((mut stream, addr)) => {
+            Ok((stream, addr)) => {
                 println!("Listener accepted client: {addr}");
                 let mut listener = listener.lock().unwrap();
-                for packet in &listener.initial_packet {
-                    let _ = stream.write_all(packet.as_bytes());
-                    sleep(Duration::from_millis(50));
-                }
-                listener.client = Some((stream, addr));
+                listener.clients.push((stream, addr));
             }
-            Err(_) => {
-                let mut listener = listener.lock().unwrap();
-                listener.client = None;
+            Err(e) => {
+                println!("Listener failed to accept client: {e}");
             }
         } 
         sleep(Duration::from_millis(10));    
@@ -97,7 +123,7 @@ fn recv(receiver: Arc<Mutex<Receiver>>, mut stream: TcpStream) {
             }
             Ok(_) => match std::str::from_utf8(&buffer) {
                 Ok(data) => {
-                    println!("Received packet from client: |{}|", data.to_string());
+                    println!("Received packet from client: |{}|", data);
                     let mut receiver = receiver.lock().unwrap();
                     let message: String = data.to_string();
                     let mut packets: Vec<String> = split_with_delimiter(message, "!");
@@ -155,7 +181,7 @@ impl From<&Vec<Interface>> for JoinCode {
             }
         }
         let port: u16 = 50000;
-        let sections: Vec<String> = ip.split('.').map(|x| String::from(x)).collect();
+        let sections: Vec<String> = ip.split('.').map(String::from).collect();
         let important_digits: String = sections[2].clone() + &sections[3];
         let dot_index: usize = sections[2].len();
         let port_index: usize = dot_index + sections[3].len();
@@ -217,7 +243,10 @@ impl Client {
             incoming: vec![],   
             outgoing,
             status: Status::Running,
-            num
+            num,
+            floor: 0,
+            transfer: None,
+            attacks: vec![]
         };
         let client = Arc::new(Mutex::new(client));
         let client_clone = Arc::clone(&client);
@@ -232,7 +261,7 @@ impl Client {
             client.socket_thread = Some(socket_thread);
             client.send_thread = Some(send_thread);
         }
-        return client;
+        client
     }
 
     pub fn send_all(&mut self, messages: &Vec<String>) {
@@ -251,12 +280,37 @@ fn receive(client: Arc<Mutex<Client>>, receiver: Arc<Mutex<Receiver>>) {
                 if receiver.status != Status::Running {
                     client.status = Status::Disconnected;
                 }
-                if client.outgoing.len() > 0 {
-                    let mut messages: Vec<String> = replace(&mut client.outgoing, vec![]);
+                if !client.outgoing.is_empty() {
+                    let mut messages: Vec<String> = take(&mut client.outgoing);
                     receiver.outgoing.append(&mut messages);
                 }
-                replace(&mut receiver.incoming, vec![])
+                take(&mut receiver.incoming)
             };
+            // Acks are never relayed, and ones arriving after their transfer has finished are dropped.
+            let transfer: &mut Option<Transfer> = &mut client.transfer;
+            incoming.retain(|packet| match parse_chunk_ack(packet) {
+                Some(chunk_id) => {
+                    if let Some(transfer) = transfer {
+                        transfer.acknowledge(chunk_id);
+                    }
+                    false
+                }
+                None => true
+            });
+            if let Some(transfer) = &mut client.transfer {
+                let mut packets: Vec<String> = transfer.poll();
+                if transfer.is_finished() {
+                    client.transfer = None;
+                }
+                client.outgoing.append(&mut packets);
+            }
+            incoming.retain(|packet| match parse_attack(packet) {
+                Some(enemy) => {
+                    client.attacks.push(enemy);
+                    false
+                }
+                None => true
+            });
             match client.player_data {
                 Some(ref mut pd) => pd.parse_updates(&incoming),
                 None => client.player_data = Some(PlayerData::new())
@@ -267,14 +321,74 @@ fn receive(client: Arc<Mutex<Client>>, receiver: Arc<Mutex<Receiver>>) {
     }
 }
 
+impl Floor {
+    fn new(tilemap: Tilemap, hazards: Vec<Hazard>, archetypes: Vec<Archetype>, director: Director, jump: JumpSettings, bosses: &[BossDefinition]) -> Self {
+        let encounter: Option<Encounter> = tilemap.arena.clone().and_then(|arena| Encounter::new(arena, bosses, &mut thread_rng()));
+        let chunks: Vec<TilemapChunk> = tilemap_chunks(&tilemap);
+        Floor {
+            tilemap_header: tilemap_header(&tilemap, chunks.len()),
+            tilemap_chunks: chunks,
+            spawn: tilemap.spawn_coordinates,
+            exit: tilemap.exit,
+            hazards: HazardTracker::new(hazards, tilemap.tilemap.clone()),
+            enemy_controller: Arc::new(Mutex::new(Controller::new(vec![], &tilemap, archetypes, director, jump, encounter))),
+            controller_thread: None,
+            enemy_thread: None,
+            active_enemies: vec![],
+            enemy_packets: vec![],
+            progression: Progression::new(tilemap.doors.clone(), tilemap.keys.clone()),
+            world_packets: vec![],
+            enemy_tiles: vec![],
+            encounter_packets: vec![],
+            debug_packets: vec![],
+            tilemap
+        }
+    }
+
+    // Changes tiles for everyone on the floor, and for anyone who joins it later.
+    fn set_tiles(&mut self, changes: &[TileChange]) {
+        if changes.is_empty() {
+            return;
+        }
+        apply_changes(&mut self.tilemap.tilemap, changes);
+        self.tilemap_chunks = tilemap_chunks(&self.tilemap);
+        self.world_packets.extend(changes.iter().map(TileChange::packet));
+    }
+
+    fn join_packets(&self) -> Vec<String> {
+        let mut outgoing: Vec<String> = vec![];
+        for enemy in &self.active_enemies {
+            outgoing.push(format!("<ne>{enemy}!"));
+        }
+        outgoing.append(&mut self.progression.join_packets());
+        outgoing.extend(self.encounter_packets.iter().cloned());
+        outgoing
+    }
+
+    fn transfer(&self, mut joining: Vec<String>) -> Transfer {
+        let mut footer: Vec<String> = vec![String::from("<tilemap_info>0!")];
+        footer.append(&mut joining);
+        footer.append(&mut self.join_packets());
+        Transfer::new(self.tilemap_header.clone(), self.tilemap_chunks.clone(), self.spawn, footer)
+    }
+}
+
 impl Server {
-    pub fn new(tilemap: Tilemap) -> Result<Arc<Mutex<Server>>, Error> {
+    pub fn new(floors: Vec<Tilemap>, debug_log: Option<String>) -> Result<Arc<Mutex<Server>>, Error> {
         if let Ok(tcp_listener) = TcpListener::bind("0.0.0.0:50000") {
-            let tilemap_packets: Vec<String> = tilemap_packet(tilemap.clone());
-            let controller = Arc::new(Mutex::new(Controller::new(vec![], tilemap.tilemap, tilemap.spawn_locations)));
-            let player_data_ref = Arc::clone(&controller);
-            let enemy_movement_ref = Arc::clone(&controller);
-            let listener = Arc::new(Mutex::new(Listener { client: None, initial_packet: tilemap_packets }));
+            let hazards: Vec<Hazard> = load_hazards("assets/data/hazards.tiledata");
+            let mut debug_log: Option<DebugLog> = debug_log.and_then(|filename| DebugLog::new(&filename));
+            if let Some(log) = &mut debug_log {
+                let maps: Vec<String> = floors.iter().enumerate().map(|(index, tilemap)| map_packet(index, &tilemap.tilemap)).collect();
+                log.write(&maps);
+            }
+            let archetypes: Vec<Archetype> = load_archetypes("assets/data/enemies.enemydata");
+            let director: DirectorSettings = load_director_settings("assets/data/director.spawndata");
+            let bosses: Vec<BossDefinition> = load_bosses("assets/data/bosses.bossdata");
+            let jump: JumpSettings = load_jump_settings("assets/data/navigation.navdata");
+            let started: Instant = Instant::now();
+            let floors: Vec<Floor> = floors.into_iter().enumerate().map(|(depth, tilemap)| Floor::new(tilemap, hazards.clone(), archetypes.clone(), Director::new(director.clone(), depth, started), jump, &bosses)).collect();
+            let listener = Arc::new(Mutex::new(Listener { clients: vec![] }));
             let accept_listener = Arc::clone(&listener);
             let listen_thread = spawn(move || {
                 listen(accept_listener, tcp_listener);
@@ -287,11 +401,9 @@ impl Server {
                 _listen_thread: Some(listen_thread),
                 running: true,
                 join_code: Some((&interfaces).into()),
-                enemy_controller: controller,
-                controller_thread: None,
-                enemy_thread: None,
-                active_enemies: vec![],
-                enemy_packets: vec![]
+                floors,
+                progression_thread: None,
+                debug_log
             };
             let server = Arc::new(Mutex::new(server));
 
@@ -303,20 +415,29 @@ impl Server {
             let distribute_thread = spawn(move || {
                 send(send_server);
             });
-            let controller_server = Arc::clone(&server);
-            let controller_thread = spawn(move || {
-                push_updates_to_enemies(controller_server, player_data_ref);
-            });
-            let server_enemy_ref = Arc::clone(&server);
-            let enemy_thread = spawn(move || {
-                enemy_cycle(server_enemy_ref, enemy_movement_ref);
+            let progression_server = Arc::clone(&server);
+            let progression_thread = spawn(move || {
+                progression_cycle(progression_server);
             });
             {
-                let mut server = server.lock().unwrap();
-                server.connection_thread = Some(connection_thread);
-                server.distribute_thread = Some(distribute_thread);
-                server.controller_thread = Some(controller_thread);
-                server.enemy_thread      = Some(enemy_thread);
+                let mut guard = server.lock().unwrap();
+                guard.connection_thread = Some(connection_thread);
+                guard.distribute_thread = Some(distribute_thread);
+                guard.progression_thread = Some(progression_thread);
+                for floor_index in 0..guard.floors.len() {
+                    let player_data_ref = Arc::clone(&guard.floors[floor_index].enemy_controller);
+                    let enemy_movement_ref = Arc::clone(&guard.floors[floor_index].enemy_controller);
+                    let controller_server = Arc::clone(&server);
+                    let controller_thread = spawn(move || {
+                        push_updates_to_enemies(controller_server, floor_index, player_data_ref);
+                    });
+                    let server_enemy_ref = Arc::clone(&server);
+                    let enemy_thread = spawn(move || {
+                        enemy_cycle(server_enemy_ref, floor_index, enemy_movement_ref);
+                    });
+                    guard.floors[floor_index].controller_thread = Some(controller_thread);
+                    guard.floors[floor_index].enemy_thread      = Some(enemy_thread);
+                }
             }
             Ok(server)
         } else {
@@ -330,6 +451,54 @@ impl Server {
             None => String::from("<NONE>"),
         }
     }
+
+    // Attacks made by players on this floor since the last call, with the attacker's current state.
+    fn floor_attacks(&self, floor: usize) -> Vec<(PlayerData, usize)> {
+        let mut attacks: Vec<(PlayerData, usize)> = vec![];
+        for client in &self.clients {
+            let mut client = client.lock().unwrap();
+            if client.floor != floor { continue; }
+            let enemies: Vec<usize> = take(&mut client.attacks);
+            if let Some(player_data) = &client.player_data {
+                attacks.extend(enemies.into_iter().map(|enemy| (player_data.clone(), enemy)));
+            }
+        }
+        attacks
+    }
+
+    // Debug frames are only built while something is listening for them.
+    fn debug_wanted(&self, floor: usize) -> bool {
+        self.debug_log.is_some() || self.floor_players(floor).iter().any(|player| player.debug)
+    }
+
+    // Changes tiles on a floor, including in the copy of the map still being sent to anyone joining it.
+    fn set_tiles(&mut self, floor: usize, changes: &[TileChange]) {
+        if changes.is_empty() {
+            return;
+        }
+        self.floors[floor].set_tiles(changes);
+        for client in &self.clients {
+            let mut client = client.lock().unwrap();
+            if client.floor != floor { continue; }
+            if let Some(transfer) = &mut client.transfer {
+                transfer.refresh(&self.floors[floor].tilemap_chunks);
+            }
+        }
+    }
+
+    fn floor_players(&self, floor: usize) -> Vec<PlayerData> {
+        let mut active_player_data: Vec<PlayerData> = vec![];
+        for client in &self.clients {
+            let client = client.lock().unwrap();
+            if client.floor != floor { continue; }
+            if let Some(player_data) = &client.player_data {
+                if player_data.username != "NONE" {
+                    active_player_data.push(player_data.clone());
+                }
+            }
+        }
+        active_player_data
+    }
 }
 
 fn accept(server: Arc<Mutex<Server>>, listener: Arc<Mutex<Listener>>) {
@@ -337,26 +506,23 @@ fn accept(server: Arc<Mutex<Server>>, listener: Arc<Mutex<Listener>>) {
     loop {
         {
             let mut server = server.lock().unwrap();
-            let client_option = {
+            let pending: Vec<(TcpStream, SocketAddr)> = {
                 let mut listener = listener.lock().unwrap();
-                replace(&mut listener.client, None)
+                take(&mut listener.clients)
             };
-            if let Some((client, addr)) = client_option {
+            for (client, addr) in pending {
                 let mut active_players: Vec<String> = vec![];
                 for client in server.clients.iter() {
                     let c = client.lock().unwrap();
+                    if c.floor != 0 { continue; }
                     if let Some(pd) = &c.player_data {
                         active_players.push(pd.username.clone());
                     }
                 }
                 let c: Arc<Mutex<Client>> = Client::new(client, addr, active_players, vec![], count);
-                let mut outgoing: Vec<String> = vec![];
-                for enemy in &server.active_enemies {
-                    outgoing.push(format!("<ne>{enemy}!"));
-                }
                 {
                     let mut tc = c.lock().unwrap();
-                    tc.send_all(&outgoing);
+                    tc.transfer = Some(server.floors[0].transfer(vec![]));
                 }
                 println!("Server polled client: {addr}");
                 server.clients.push(c);
@@ -375,37 +541,52 @@ fn send(server: Arc<Mutex<Server>>) {
     let mut start_time = Instant::now();
     let run_duration = Duration::from_secs(1);
     loop {
-        let mut packets: Vec<String> = vec![];
         {
             let mut server = server.lock().unwrap();
-            packets.append(&mut server.enemy_packets);
             if !server.running { break; }
+            let server = &mut *server;
+            let mut packets: Vec<Vec<String>> = vec![vec![]; server.floors.len()];
+            for (floor, floor_packets) in server.floors.iter_mut().zip(packets.iter_mut()) {
+                floor_packets.append(&mut floor.enemy_packets);
+                floor_packets.append(&mut floor.world_packets);
+            }
             for client in &server.clients {
                 let mut client = client.lock().unwrap();
-                packets.append(&mut client.incoming);
-                let _ = replace(&mut client.incoming, vec![]);
+                let floor: usize = client.floor;
+                packets[floor].append(&mut client.incoming);
             }
             let mut to_remove: Vec<usize> = vec![];
-            let mut disconnect: Vec<String> = vec![];
+            let mut disconnect: Vec<Vec<String>> = vec![vec![]; server.floors.len()];
             let mut count: usize = 0;
             if Instant::now().duration_since(start_time) > run_duration {
                 start_time = Instant::now();
-                let mut enemy_join_packets: Vec<String> = vec![];
-                for enemy in &server.active_enemies {
-                    enemy_join_packets.push(format!("<ne>{enemy}!"));
+                for (floor, floor_packets) in server.floors.iter().zip(packets.iter_mut()) {
+                    for enemy in &floor.active_enemies {
+                        floor_packets.push(format!("<ne>{enemy}!"));
+                    }
                 }
-                packets.append(&mut enemy_join_packets);
+            }
+            let mut debug: Vec<Vec<String>> = vec![vec![]; server.floors.len()];
+            for (floor, floor_debug) in server.floors.iter_mut().zip(debug.iter_mut()) {
+                floor_debug.append(&mut floor.debug_packets);
+            }
+            if let Some(log) = &mut server.debug_log {
+                log.write(&debug.concat());
             }
             for client in &server.clients {
                 let mut c = client.lock().unwrap();
-                c.send_all(&packets);
+                let floor: usize = c.floor;
+                c.send_all(&packets[floor]);
+                if c.player_data.as_ref().is_some_and(|pd| pd.debug) {
+                    c.send_all(&debug[floor]);
+                }
             }
             for client in &server.clients {
                 let c = client.lock().unwrap();
                 if c.status != Status::Running {
                     to_remove.push(count);
                     if let Some(pd) = &c.player_data {
-                        disconnect.push(format!("<pdis>{}!", pd.username));
+                        disconnect[c.floor].push(format!("<pdis>{}!", pd.username));
                     }
                 }
                 else {
@@ -414,7 +595,8 @@ fn send(server: Arc<Mutex<Server>>) {
             }
             for client in &server.clients {
                 let mut c = client.lock().unwrap();
-                c.send_all(&disconnect);
+                let floor: usize = c.floor;
+                c.send_all(&disconnect[floor]);
             }
             for index in to_remove {
                 server.clients.remove(index);
@@ -424,38 +606,34 @@ fn send(server: Arc<Mutex<Server>>) {
     }
 }
 
-fn push_updates_to_enemies(server: Arc<Mutex<Server>>, controller: Arc<Mutex<Controller>>) {
+fn push_updates_to_enemies(server: Arc<Mutex<Server>>, floor: usize, controller: Arc<Mutex<Controller>>) {
     loop {
-        let mut active_player_data: Vec<PlayerData> = vec![];
-        {
+        let (active_player_data, debug): (Vec<PlayerData>, bool) = {
             let server = server.lock().unwrap();
             if !server.running { break; }
-            for client in &server.clients {
-                let client = client.lock().unwrap();
-                println!("Investigating client!");
-                if let Some(player_data) = &client.player_data {
-                    if player_data.username != "NONE" {
-                        println!("Client has data!");
-                        active_player_data.push(player_data.clone());
-                    }
-                }
-            }
-        }
+            (server.floor_players(floor), server.debug_wanted(floor))
+        };
         {
             let mut controller = controller.lock().unwrap();
             controller.update_players(active_player_data);
-            let new_enemy: Option<String> = controller.update_enemies();
-            if let Some(new_enemy) = new_enemy {
-                let mut server = server.lock().unwrap();
-                server.active_enemies.push(new_enemy.clone());
-                server.enemy_packets.push(format!("<ne>{new_enemy}!"));
+            let new_enemies: Vec<String> = controller.update_enemies();
+            let despawned: Vec<String> = controller.despawn_enemies();
+            let mut server = server.lock().unwrap();
+            server.floors[floor].active_enemies.retain(|enemy| !despawned.contains(enemy));
+            for new_enemy in new_enemies {
+                server.floors[floor].active_enemies.push(new_enemy.clone());
+                server.floors[floor].enemy_packets.push(format!("<ne>{new_enemy}!"));
+            }
+            if debug {
+                let mut packets: Vec<String> = controller.debug_packets(floor);
+                server.floors[floor].debug_packets.append(&mut packets);
             }
         }
         sleep(Duration::from_millis(500));
     }
 }
 
-fn enemy_cycle(server: Arc<Mutex<Server>>, controller: Arc<Mutex<Controller>>) {
+fn enemy_cycle(server: Arc<Mutex<Server>>, floor: usize, controller: Arc<Mutex<Controller>>) {
     let mut previous_time = Instant::now();
     loop {
         let current_time = Instant::now();
@@ -465,8 +643,64 @@ fn enemy_cycle(server: Arc<Mutex<Server>>, controller: Arc<Mutex<Controller>>) {
             let mut controller = controller.lock().unwrap();
             controller.move_enemies(deltatime);
             let mut server = server.lock().unwrap();
-            server.enemy_packets.append(&mut controller.packets);
+            let attacks: Vec<(PlayerData, usize)> = server.floor_attacks(floor);
+            let dead: Vec<String> = controller.resolve_attacks(attacks);
+            server.floors[floor].active_enemies.retain(|enemy| !dead.contains(enemy));
+            controller.set_tiles(&take(&mut server.floors[floor].enemy_tiles));
+            let changes: Vec<TileChange> = take(&mut controller.tile_changes);
+            server.set_tiles(floor, &changes);
+            server.floors[floor].enemy_packets.append(&mut controller.packets);
+            server.floors[floor].encounter_packets = controller.encounter_packets();
         }
         sleep(Duration::from_millis(16));
     }
 }
+
+fn progression_cycle(server: Arc<Mutex<Server>>) {
+    loop {
+        {
+            let mut server = server.lock().unwrap();
+            if !server.running { break; }
+            for floor in 0..server.floors.len() {
+                let players: Vec<PlayerData> = server.floor_players(floor);
+                let mut packets: Vec<String> = server.floors[floor].progression.update(&players);
+                server.floors[floor].world_packets.append(&mut packets);
+                let changes: Vec<TileChange> = take(&mut server.floors[floor].progression.changes);
+                server.set_tiles(floor, &changes);
+                server.floors[floor].enemy_tiles.extend(changes);
+                let mut packets: Vec<String> = server.floors[floor].hazards.update(&players);
+                server.floors[floor].world_packets.append(&mut packets);
+            }
+
+            let server = &mut *server;
+            let mut descending: Vec<(Arc<Mutex<Client>>, String, usize)> = vec![];
+            for client in &server.clients {
+                let c = client.lock().unwrap();
+                if c.floor + 1 >= server.floors.len() { continue; }
+                if let (Some(pd), Some(exit)) = (&c.player_data, server.floors[c.floor].exit) {
+                    if pd.username != "NONE" && (Vector::from(exit) - &pd.position).magnitude <= 1.0f32 {
+                        descending.push((Arc::clone(client), pd.username.clone(), c.floor));
+                    }
+                }
+            }
+            for (client, username, floor) in descending {
+                println!("{username} descended to floor {}", floor + 1);
+                let mut joining: Vec<String> = vec![];
+                for other in &server.clients {
+                    let other = other.lock().unwrap();
+                    if other.floor != floor + 1 { continue; }
+                    if let Some(pd) = &other.player_data {
+                        joining.push(format!("<pcon>{}!", pd.username));
+                    }
+                }
+                let transfer: Transfer = server.floors[floor + 1].transfer(joining);
+                server.floors[floor].world_packets.push(format!("<pdis>{username}!"));
+                server.floors[floor + 1].world_packets.push(format!("<pcon>{username}!"));
+                let mut client = client.lock().unwrap();
+                client.floor = floor + 1;
+                client.transfer = Some(transfer);
+            }
+        }
+        sleep(Duration::from_millis(100));
+    }
+}
@@ -3,22 +3,24 @@ use crate::{tilemap::Tilemap, vector::Vector};
 #[derive(Clone)]
 pub struct PlayerData {
     pub position: Vector,
-    crouching: bool,
+    pub crouching: bool,
     frame: i8,
     direction: i8,
-    pub username: String
+    pub username: String,
+    pub party: String,
+    pub debug: bool
 }
 
 impl PlayerData {
     pub fn new() -> Self {
-        PlayerData { position: Vector::component(0f32, 0f32), crouching: false, frame: 0, direction: 0, username: String::from("NONE") }
+        PlayerData { position: Vector::component(0f32, 0f32), crouching: false, frame: 0, direction: 0, username: String::from("NONE"), party: String::from("default"), debug: false }
     }
     pub fn parse_updates(&mut self, packets: &Vec<String>) {
         for packet in packets {
-            let components: Vec<String> = packet.split('!').map(|x| String::from(x)).collect::<Vec<String>>()[0].split('>').map(|x| String::from(x)).collect();
+            let components: Vec<String> = packet.split('!').map(String::from).collect::<Vec<String>>()[0].split('>').map(String::from).collect();
             let mut identifier: String = components[0].clone();
             identifier.remove(0);
-            let data: Vec<String> = components[1].split(',').map(|x| String::from(x)).collect::<Vec<String>>();
+            let data: Vec<String> = components[1].split(',').map(String::from).collect::<Vec<String>>();
             self.username = data[0].clone();
             if identifier == "pp" {
                 if let (Ok(x), Ok(y)) = (data[1].parse::<f32>(), data[2].parse::<f32>()) {
@@ -38,28 +40,91 @@ impl PlayerData {
             if identifier == "pc" {
                 self.crouching = data[1] == "1";
             }
+            if identifier == "pt" {
+                self.party = data[1].clone();
+            }
+            if identifier == "dbg" {
+                self.debug = data[1] == "1";
+            }
         }
     }
 }
 
-pub fn tilemap_packet(tilemap: Tilemap) -> Vec<String> {
-    let mut transmissions: Vec<String> = vec![
-            String::from("<tilemap_info>1!"),
-            format!("<sp>{},{}!", tilemap.spawn_coordinates[0], tilemap.spawn_coordinates[1])
-        ];
-    for row in &tilemap.tilemap {
-        let mut delim: String = String::new();
-        for item in row {
-            delim += &item.to_string();
-            delim.push(',');
+pub const CHUNK_SIZE: usize = 16;
+
+#[derive(Clone)]
+pub struct TilemapChunk {
+    pub id: usize,
+    pub x: usize,
+    pub y: usize,
+    pub packet: String
+}
+
+// Runs of identical tiles are written as "tile:count", single tiles as "tile", separated by ';'.
+pub fn compress_tiles(tiles: &[usize]) -> String {
+    let mut runs: Vec<String> = vec![];
+    let mut index: usize = 0;
+    while index < tiles.len() {
+        let tile: usize = tiles[index];
+        let mut count: usize = 1;
+        while index + count < tiles.len() && tiles[index + count] == tile {
+            count += 1;
+        }
+        if count == 1 {
+            runs.push(tile.to_string());
+        } else {
+            runs.push(format!("{tile}:{count}"));
         }
-        let mut temp: Vec<char> = delim.chars().collect();
-        if let Some(last) = temp.last_mut() {
-            *last = '!';
+        index += count;
+    }
+    runs.join(";")
+}
+
+pub fn tilemap_header(tilemap: &Tilemap, chunk_count: usize) -> Vec<String> {
+    let height: usize = tilemap.tilemap.len();
+    let width: usize = tilemap.tilemap.first().map_or(0, |row| row.len());
+    vec![
+        String::from("<tilemap_info>1!"),
+        format!("<tms>{width},{height},{CHUNK_SIZE},{chunk_count}!"),
+        format!("<sp>{},{}!", tilemap.spawn_coordinates[0], tilemap.spawn_coordinates[1])
+    ]
+}
+
+pub fn tilemap_chunks(tilemap: &Tilemap) -> Vec<TilemapChunk> {
+    let height: usize = tilemap.tilemap.len();
+    let width: usize = tilemap.tilemap.first().map_or(0, |row| row.len());
+    let chunks_x: usize = width.div_ceil(CHUNK_SIZE);
+    let chunks_y: usize = height.div_ceil(CHUNK_SIZE);
+    let mut chunks: Vec<TilemapChunk> = vec![];
+    for chunk_y in 0..chunks_y {
+        for chunk_x in 0..chunks_x {
+            let x: usize = chunk_x * CHUNK_SIZE;
+            let y: usize = chunk_y * CHUNK_SIZE;
+            let chunk_width: usize = CHUNK_SIZE.min(width - x);
+            let chunk_height: usize = CHUNK_SIZE.min(height - y);
+            let mut tiles: Vec<usize> = vec![];
+            for row in &tilemap.tilemap[y..y + chunk_height] {
+                tiles.extend_from_slice(&row[x..x + chunk_width]);
+            }
+            let id: usize = chunk_y * chunks_x + chunk_x;
+            chunks.push(TilemapChunk {
+                id,
+                x,
+                y,
+                packet: format!("<tmc>{id},{x},{y},{chunk_width},{chunk_height},{}!", compress_tiles(&tiles))
+            });
         }
-        delim = temp.into_iter().collect();
-        transmissions.push(format!("<tmr>{delim}"));
     }
-    transmissions.push(String::from("<tilemap_info>0!"));
-    transmissions
-}
\ No newline at end of file
+    chunks
+}
+
+pub fn parse_chunk_ack(packet: &str) -> Option<usize> {
+    let data: &str = packet.strip_prefix("<ca>")?.strip_suffix('!')?;
+    data.split(',').nth(1)?.parse::<usize>().ok()
+}
+
+// <pa>username,enemy_uuid! is sent by a client when its player swings at an enemy.
+pub fn parse_attack(packet: &str) -> Option<usize> {
+    let data: &str = packet.strip_prefix("<pa>")?.strip_suffix('!')?;
+    data.split(',').nth(1)?.parse::<usize>().ok()
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Instant, Duration};
+use crate::astar::{Ai, Position};
+use crate::flowfield::FlowField;
+use crate::hierarchy::{HierarchicalPathfinder, RoomGraph};
+use crate::navgraph::{EdgeKind, NavGraph};
+use crate::smoothing::smooth_route;
+use crate::vector::Vector;
+
+const WORKERS: usize = 2;
+const TICK: Duration = Duration::from_millis(16);
+// Time each worker may spend searching per tick before it yields.
+const BUDGET: Duration = Duration::from_millis(4);
+const CACHE_TTL: Duration = Duration::from_millis(1000);
+const CACHE_SIZE: usize = 512;
+struct PathRequest {
+    enemy: usize,
+    generation: usize,
+    version: usize,
+    start: Position,
+    goal: Position,
+    ai: Ai,
+    // The player whose current position is the goal, if it is one.
+    target: Option<String>
+}
+
+// Ground routes carry the kind of move into each waypoint, other routes leave moves empty.
+#[derive(Clone)]
+pub struct Route {
+    pub path: Vec<Vector>,
+    pub moves: Vec<EdgeKind>
+}
+
+pub struct PathResult {
+    pub enemy: usize,
+    generation: usize,
+    pub route: Option<Route>
+}
+
+// The map searches run on, replaced whenever a tile changes.
+struct MapState {
+    tilemap: Vec<Vec<usize>>,
+    nav: NavGraph,
+    version: usize
+}
+
+// A flow field towards one player for one Ai, built once per controller update and followed by every
+// enemy of that Ai chasing them. Ground enemies path over the nav graph instead, for its jumps.
+struct SharedField {
+    update: usize,
+    version: usize,
+    // None while a worker is still building it.
+    field: Option<Arc<FlowField>>
+}
+
+// The player chased and the Ai chasing them.
+type FieldKey = (String, Ai);
+
+enum Shared {
+    None,
+    Build(FieldKey),
+    Ready(Arc<FlowField>)
+}
+
+struct Queue {
+    requests: VecDeque<PathRequest>,
+    latest: HashMap<usize, usize>,
+    results: Vec<PathResult>,
+    cache: HashMap<(Ai, Position, Position), (Instant, Option<Route>)>,
+    fields: HashMap<FieldKey, SharedField>,
+    update: usize,
+    // Routes found on an older map are still handed back, but never cached.
+    map: Arc<MapState>,
+    running: bool
+}
+
+// Runs path searches off the controller's thread. Only the newest request per enemy is answered, so a
+// request made for a target that has since moved is dropped once a new one replaces it.
+pub struct PathService {
+    queue: Arc<Mutex<Queue>>,
+    generation: usize,
+    _workers: Vec<JoinHandle<()>>
+}
+
+impl Queue {
+    fn is_current(&self, request: &PathRequest) -> bool {
+        self.latest.get(&request.enemy) == Some(&request.generation)
+    }
+
+    fn cached(&self, request: &PathRequest) -> Option<Option<Route>> {
+        match self.cache.get(&(request.ai, request.start.clone(), request.goal.clone())) {
+            Some((time, route)) if time.elapsed() < CACHE_TTL => Some(route.clone()),
+            _ => None
+        }
+    }
+
+    // The next request still worth solving, along with every queued request heading for the same goal
+    // or chasing the same player. Requests waiting on a field another worker is building are skipped.
+    fn next_batch(&mut self) -> (Vec<PathRequest>, Shared) {
+        let mut waiting: VecDeque<PathRequest> = VecDeque::new();
+        let mut found: Option<(PathRequest, Shared)> = None;
+        while let Some(request) = self.requests.pop_front() {
+            if !self.is_current(&request) { continue; }
+            if let Some(route) = self.cached(&request) {
+                self.results.push(PathResult { enemy: request.enemy, generation: request.generation, route });
+                continue;
+            }
+            let key: Option<FieldKey> = request.target.clone().filter(|_| request.ai != Ai::Ground).map(|target| (target, request.ai));
+            let Some(key) = key else {
+                found = Some((request, Shared::None));
+                break;
+            };
+            let (update, version): (usize, usize) = (self.update, self.map.version);
+            match self.fields.get(&key) {
+                Some(shared) if shared.update == update && shared.version == version => match &shared.field {
+                    Some(field) => found = Some((request, Shared::Ready(Arc::clone(field)))),
+                    None => {
+                        waiting.push_back(request);
+                        continue;
+                    }
+                },
+                _ => {
+                    self.fields.insert(key.clone(), SharedField { update, version, field: None });
+                    found = Some((request, Shared::Build(key)));
+                }
+            }
+            break;
+        }
+        let Some((request, shared)) = found else {
+            self.requests = waiting;
+            return (vec![], Shared::None);
+        };
+        let mut batch: Vec<PathRequest> = vec![];
+        for other in self.requests.drain(..) {
+            let same: bool = match &shared {
+                Shared::None => other.target.is_none() && other.ai == request.ai && other.goal == request.goal,
+                _ => other.ai == request.ai && other.target == request.target
+            };
+            if same {
+                batch.push(other);
+            } else {
+                waiting.push_back(other);
+            }
+        }
+        self.requests = waiting;
+        batch.insert(0, request);
+        (batch, shared)
+    }
+
+    fn store(&mut self, request: PathRequest, route: Option<Route>) {
+        if self.cache.len() >= CACHE_SIZE {
+            self.cache.retain(|_, (time, _)| time.elapsed() < CACHE_TTL);
+            if self.cache.len() >= CACHE_SIZE {
+                self.cache.clear();
+            }
+        }
+        if request.version == self.map.version {
+            self.cache.insert((request.ai, request.start.clone(), request.goal.clone()), (Instant::now(), route.clone()));
+        }
+        if self.is_current(&request) {
+            self.results.push(PathResult { enemy: request.enemy, generation: request.generation, route });
+        }
+    }
+}
+
+fn solve(tilemap: &[Vec<usize>], nav: &NavGraph, pathfinder: &mut HierarchicalPathfinder, batch: &[PathRequest]) -> Vec<Option<Route>> {
+    let ai: Ai = batch[0].ai;
+    if ai == Ai::Ground {
+        return batch.iter().map(|request| {
+            nav.find_path(request.start.clone(), request.goal.clone()).map(|route| {
+                let (path, moves): (Vec<Vector>, Vec<EdgeKind>) = route.into_iter().unzip();
+                Route { path, moves }
+            })
+        }).collect();
+    }
+    let plain = |path: Option<Vec<Vector>>| path.map(|path| Route { path, moves: vec![] });
+    batch.iter().map(|request| plain(pathfinder.find_path(tilemap, request.start.clone(), request.goal.clone(), &ai))).collect()
+}
+
+fn follow(tilemap: &[Vec<usize>], field: &FlowField, batch: &[PathRequest]) -> Vec<Option<Route>> {
+    batch.iter().map(|request| field.path(tilemap, request.start.clone(), &request.ai).map(|path| Route { path, moves: vec![] })).collect()
+}
+
+fn worker(queue: Arc<Mutex<Queue>>, mut pathfinder: HierarchicalPathfinder) {
+    let mut version: usize = 0;
+    loop {
+        let tick_start: Instant = Instant::now();
+        while tick_start.elapsed() < BUDGET {
+            let (mut batch, shared, map): (Vec<PathRequest>, Shared, Arc<MapState>) = {
+                let mut queue = queue.lock().unwrap();
+                if !queue.running { return; }
+                let (batch, shared): (Vec<PathRequest>, Shared) = queue.next_batch();
+                (batch, shared, Arc::clone(&queue.map))
+            };
+            if batch.is_empty() { break; }
+            if map.version != version {
+                version = map.version;
+                pathfinder.clear();
+            }
+            for request in &mut batch {
+                request.version = version;
+            }
+            let ai: Ai = batch[0].ai;
+            let mut built: Option<(FieldKey, Arc<FlowField>)> = None;
+            let routes: Vec<Option<Route>> = match shared {
+                Shared::None => solve(&map.tilemap, &map.nav, &mut pathfinder, &batch),
+                Shared::Ready(field) => follow(&map.tilemap, &field, &batch),
+                Shared::Build(key) => {
+                    let field: Arc<FlowField> = Arc::new(FlowField::new(&map.tilemap, batch[0].goal.clone(), &ai));
+                    let routes: Vec<Option<Route>> = follow(&map.tilemap, &field, &batch);
+                    built = Some((key, field));
+                    routes
+                }
+            };
+            let routes: Vec<Option<Route>> = routes.into_iter().map(|route| route.map(|route| smooth_route(&map.tilemap, route, &ai))).collect();
+            let mut queue = queue.lock().unwrap();
+            if let Some((key, field)) = built {
+                if let Some(shared) = queue.fields.get_mut(&key) {
+                    shared.field.get_or_insert(field);
+                }
+            }
+            for (request, route) in batch.into_iter().zip(routes) {
+                queue.store(request, route);
+            }
+        }
+        sleep(TICK.saturating_sub(tick_start.elapsed()));
+    }
+}
+
+impl PathService {
+    pub fn new(tilemap: Vec<Vec<usize>>, graph: RoomGraph, nav: NavGraph) -> Self {
+        let queue: Arc<Mutex<Queue>> = Arc::new(Mutex::new(Queue { requests: VecDeque::new(), latest: HashMap::new(), results: vec![], cache: HashMap::new(), fields: HashMap::new(), update: 0, map: Arc::new(MapState { tilemap, nav, version: 0 }), running: true }));
+        let mut workers: Vec<JoinHandle<()>> = vec![];
+        for _ in 0..WORKERS {
+            let queue: Arc<Mutex<Queue>> = Arc::clone(&queue);
+            let pathfinder: HierarchicalPathfinder = HierarchicalPathfinder::new(graph.clone());
+            workers.push(spawn(move || worker(queue, pathfinder)));
+        }
+        PathService { queue, generation: 0, _workers: workers }
+    }
+
+    // Swaps in a map with changed tiles. Everything cached from the old one is thrown away.
+    pub fn update_map(&mut self, tilemap: Vec<Vec<usize>>, nav: NavGraph) {
+        let mut queue = self.queue.lock().unwrap();
+        let version: usize = queue.map.version + 1;
+        queue.map = Arc::new(MapState { tilemap, nav, version });
+        queue.cache.clear();
+    }
+
+    // Starts a controller update. Flow fields from the last one are dropped, as their players have moved.
+    pub fn begin_update(&mut self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.update += 1;
+        queue.fields.clear();
+    }
+
+    // Replaces any request still queued for this enemy. `target` names the player whose position is the
+    // goal, so that everyone chasing them can share one flow field.
+    pub fn request(&mut self, enemy: usize, start: Position, goal: Position, ai: Ai, target: Option<String>) {
+        self.generation += 1;
+        let mut queue = self.queue.lock().unwrap();
+        queue.latest.insert(enemy, self.generation);
+        queue.requests.push_back(PathRequest { enemy, generation: self.generation, version: 0, start, goal, ai, target });
+    }
+
+    // Drops anything queued or computed for an enemy that no longer exists.
+    pub fn cancel(&mut self, enemy: usize) {
+        self.queue.lock().unwrap().latest.remove(&enemy);
+    }
+
+    pub fn results(&mut self) -> Vec<PathResult> {
+        let mut queue = self.queue.lock().unwrap();
+        let results: Vec<PathResult> = queue.results.drain(..).collect();
+        results.into_iter().filter(|result| queue.latest.get(&result.enemy) == Some(&result.generation)).collect()
+    }
+}
+
+impl Drop for PathService {
+    fn drop(&mut self) {
+        self.queue.lock().unwrap().running = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::navgraph::JumpSettings;
+
+    fn request(enemy: usize, start: [usize; 2], target: &str) -> PathRequest {
+        PathRequest { enemy, generation: enemy, version: 0, start: start.into(), goal: Position::new(6, 1), ai: Ai::Spider, target: Some(String::from(target)) }
+    }
+
+    #[test]
+    fn chasers_share_one_field_per_update() {
+        let tilemap: Vec<Vec<usize>> = vec![vec![2; 8]; 8];
+        let nav: NavGraph = NavGraph::new(&tilemap, JumpSettings::default());
+        let mut queue: Queue = Queue { requests: VecDeque::new(), latest: HashMap::new(), results: vec![], cache: HashMap::new(), fields: HashMap::new(), update: 1, map: Arc::new(MapState { tilemap: tilemap.clone(), nav, version: 0 }), running: true };
+        for (enemy, start) in [(1, [1, 1]), (2, [1, 6]), (3, [3, 4])] {
+            queue.latest.insert(enemy, enemy);
+            queue.requests.push_back(request(enemy, start, "alice"));
+        }
+
+        // All three chasers come out together to build one field, and a later one waits for it.
+        let (batch, shared): (Vec<PathRequest>, Shared) = queue.next_batch();
+        assert_eq!(batch.len(), 3);
+        let Shared::Build(key) = shared else { panic!("expected a field to build") };
+        queue.latest.insert(4, 4);
+        queue.requests.push_back(request(4, [5, 5], "alice"));
+        assert!(queue.next_batch().0.is_empty());
+
+        queue.fields.get_mut(&key).unwrap().field = Some(Arc::new(FlowField::new(&tilemap, Position::new(6, 1), &Ai::Spider)));
+        let (batch, shared): (Vec<PathRequest>, Shared) = queue.next_batch();
+        assert!(batch.len() == 1 && matches!(shared, Shared::Ready(_)));
+
+        // The next update builds afresh, as alice has moved on.
+        queue.update += 1;
+        queue.latest.insert(5, 5);
+        queue.requests.push_back(request(5, [1, 1], "alice"));
+        assert!(matches!(queue.next_batch().1, Shared::Build(_)));
+    }
+}
@@ -0,0 +1,73 @@
+use std::f32::consts::PI;
+use std::time::{Instant, Duration};
+use crate::astar::is_solid;
+use crate::packet::PlayerData;
+use crate::smoothing::tiles_crossed;
+use crate::vector::Vector;
+
+// Anything this close is noticed whichever way the enemy faces.
+const TOUCH_RANGE: f32 = 1.5f32;
+// Crouching players make this much of the noise of a walking one.
+const CROUCH_NOISE: f32 = 0.35f32;
+// Players moving less than this between updates make no noise at all.
+pub const STILL: f32 = 0.05f32;
+
+#[derive(Clone, Copy)]
+pub struct Senses {
+    pub sight: f32,
+    pub fov: f32,
+    pub hearing: f32,
+    pub memory: Duration
+}
+
+impl Default for Senses {
+    fn default() -> Self {
+        Senses { sight: 12f32, fov: 2f32 * PI / 3f32, hearing: 6f32, memory: Duration::from_millis(5000) }
+    }
+}
+
+// Where a player was last seen or heard.
+#[derive(Clone)]
+pub struct Memory {
+    pub username: String,
+    pub position: Vector,
+    pub time: Instant
+}
+
+pub fn line_of_sight(tilemap: &[Vec<usize>], from: &Vector, to: &Vector) -> bool {
+    tiles_crossed(&Vector::component(from.x.round(), from.y.round()), &Vector::component(to.x.round(), to.y.round())).into_iter().all(|[x, y]| {
+        y >= 0 && x >= 0 && (y as usize) < tilemap.len() && (x as usize) < tilemap[y as usize].len() && !is_solid(tilemap[y as usize][x as usize])
+    })
+}
+
+fn in_cone(facing: f32, fov: f32, from: &Vector, to: &Vector) -> bool {
+    let direction: f32 = (to.y - from.y).atan2(to.x - from.x);
+    let mut difference: f32 = (direction - facing).abs() % (2f32 * PI);
+    if difference > PI {
+        difference = 2f32 * PI - difference;
+    }
+    difference <= fov / 2f32
+}
+
+impl Senses {
+    pub fn can_see(&self, tilemap: &[Vec<usize>], position: &Vector, facing: f32, player: &PlayerData) -> bool {
+        let distance: f32 = (&player.position - position).magnitude;
+        if distance <= TOUCH_RANGE {
+            return true;
+        }
+        distance <= self.sight && in_cone(facing, self.fov, position, &player.position) && line_of_sight(tilemap, position, &player.position)
+    }
+
+    // Hearing goes through walls, but only moving players are heard and crouching ones much less so.
+    pub fn can_hear(&self, position: &Vector, player: &PlayerData, moved: f32) -> bool {
+        if moved < STILL {
+            return false;
+        }
+        let radius: f32 = if player.crouching { self.hearing * CROUCH_NOISE } else { self.hearing };
+        (&player.position - position).magnitude <= radius
+    }
+
+    pub fn remembers(&self, memory: &Memory) -> bool {
+        memory.time.elapsed() < self.memory
+    }
+}
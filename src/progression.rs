@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use rand::Rng;
+use rand::rngs::ThreadRng;
+use rand::seq::SliceRandom;
+use crate::packet::PlayerData;
+use crate::tilemap::{Tilemap, TileChange, DOOR_TILE};
+use crate::vector::Vector;
+
+// A door closes its corridor with solid door tiles. `tiles` holds what they replaced, put back once
+// the door is unlocked.
+#[derive(Clone)]
+pub struct Door {
+    pub id: usize,
+    pub connection: usize,
+    pub position: [usize; 2],
+    pub tiles: Vec<TileChange>
+}
+
+// A key shares its id with the door it opens.
+#[derive(Clone)]
+pub struct Key {
+    pub id: usize,
+    pub room: usize,
+    pub position: [usize; 2]
+}
+
+#[derive(Default)]
+pub struct Party {
+    pub keys: Vec<usize>,
+    pub unlocked: Vec<usize>
+}
+
+// Parties keep their own keys, but a door is a wall in the shared map, so the first party to unlock it
+// opens it for everyone.
+pub struct Progression {
+    doors: Vec<Door>,
+    keys: Vec<Key>,
+    parties: HashMap<String, Party>,
+    pub changes: Vec<TileChange>
+}
+
+fn reachable_rooms(tilemap: &Tilemap, locked: &[usize]) -> Vec<bool> {
+    let mut reached: Vec<bool> = vec![false; tilemap.rooms.len()];
+    if tilemap.spawn_room >= reached.len() {
+        return reached;
+    }
+    let mut stack: Vec<usize> = vec![tilemap.spawn_room];
+    reached[tilemap.spawn_room] = true;
+    while let Some(room) = stack.pop() {
+        for (index, connection) in tilemap.connections.iter().enumerate() {
+            if locked.contains(&index) { continue; }
+            let other: usize = if connection.rooms[0] == room {
+                connection.rooms[1]
+            } else if connection.rooms[1] == room {
+                connection.rooms[0]
+            } else {
+                continue;
+            };
+            if !reached[other] {
+                reached[other] = true;
+                stack.push(other);
+            }
+        }
+    }
+    reached
+}
+
+fn key_position(tilemap: &Tilemap, room: usize, rng: &mut ThreadRng) -> [usize; 2] {
+    match tilemap.standable_tiles(room).choose(rng) {
+        Some(position) => *position,
+        None => tilemap.spawn_locations[room]
+    }
+}
+
+pub fn place_locks(tilemap: &mut Tilemap, rng: &mut ThreadRng) {
+    if tilemap.connections.len() < 2 {
+        return;
+    }
+    let mut locked: Vec<usize> = (0..tilemap.connections.len()).collect();
+    locked.shuffle(rng);
+    locked.truncate((tilemap.connections.len() / 3).max(1));
+
+    // Door N's key only ever goes in a room reachable while doors N.. are still shut.
+    for id in 0..locked.len() {
+        let reached: Vec<bool> = reachable_rooms(tilemap, &locked[id..]);
+        let rooms: Vec<usize> = (0..reached.len()).filter(|room| reached[*room]).collect();
+        let room: usize = rooms[rng.gen_range(0..rooms.len())];
+        let path = &tilemap.connections[locked[id]].path;
+        let door_cell: [usize; 2] = tilemap.door_cell(locked[id]).unwrap_or([path[path.len() / 2].x, path[path.len() / 2].y]);
+        tilemap.doors.push(Door { id, connection: locked[id], position: door_cell, tiles: vec![] });
+        let position: [usize; 2] = key_position(tilemap, room, rng);
+        tilemap.keys.push(Key { id, room, position });
+    }
+
+    if !is_solvable(tilemap) {
+        tilemap.doors.clear();
+        tilemap.keys.clear();
+    }
+    for index in 0..tilemap.doors.len() {
+        let tiles: Vec<TileChange> = tilemap.door_span(tilemap.doors[index].position);
+        for tile in &tiles {
+            tilemap.tilemap[tile.position[1]][tile.position[0]] = DOOR_TILE;
+        }
+        tilemap.doors[index].tiles = tiles;
+    }
+}
+
+pub fn is_solvable(tilemap: &Tilemap) -> bool {
+    let mut locked: Vec<usize> = tilemap.doors.iter().map(|door| door.connection).collect();
+    loop {
+        if locked.is_empty() {
+            return true;
+        }
+        let reached: Vec<bool> = reachable_rooms(tilemap, &locked);
+        let before: usize = locked.len();
+        for key in &tilemap.keys {
+            if !reached[key.room] { continue; }
+            if let Some(door) = tilemap.doors.iter().find(|door| door.id == key.id) {
+                locked.retain(|connection| *connection != door.connection);
+            }
+        }
+        if locked.len() == before {
+            return false;
+        }
+    }
+}
+
+impl Progression {
+    pub fn new(doors: Vec<Door>, keys: Vec<Key>) -> Self {
+        Progression { doors, keys, parties: HashMap::new(), changes: vec![] }
+    }
+
+    pub fn join_packets(&self) -> Vec<String> {
+        let mut packets: Vec<String> = vec![];
+        for door in &self.doors {
+            packets.push(format!("<door>{},{},{}!", door.id, door.position[0], door.position[1]));
+        }
+        for key in &self.keys {
+            packets.push(format!("<key>{},{},{}!", key.id, key.position[0], key.position[1]));
+        }
+        for (name, party) in &self.parties {
+            for key in &party.keys {
+                packets.push(format!("<kp>{name},{key},NONE!"));
+            }
+            for door in &party.unlocked {
+                packets.push(format!("<du>{name},{door},NONE!"));
+            }
+        }
+        packets
+    }
+
+    fn is_open(&self, door: usize) -> bool {
+        self.parties.values().any(|party| party.unlocked.contains(&door))
+    }
+
+    // Unlocked doors have their tiles queued in `changes`.
+    pub fn update(&mut self, players: &[PlayerData]) -> Vec<String> {
+        let mut packets: Vec<String> = vec![];
+        for player in players {
+            let opened: Vec<usize> = self.doors.iter().map(|door| door.id).filter(|door| self.is_open(*door)).collect();
+            let party: &mut Party = self.parties.entry(player.party.clone()).or_default();
+            for key in &self.keys {
+                if party.keys.contains(&key.id) { continue; }
+                if (Vector::from(key.position) - &player.position).magnitude <= 1.0f32 {
+                    party.keys.push(key.id);
+                    packets.push(format!("<kp>{},{},{}!", player.party, key.id, player.username));
+                }
+            }
+            for door in &self.doors {
+                if party.unlocked.contains(&door.id) || !party.keys.contains(&door.id) { continue; }
+                // The door fills a tile either side of its cell, so it is opened from just beyond that.
+                if (Vector::from(door.position) - &player.position).magnitude <= 3.0f32 {
+                    party.unlocked.push(door.id);
+                    packets.push(format!("<du>{},{},{}!", player.party, door.id, player.username));
+                    if !opened.contains(&door.id) {
+                        self.changes.extend(door.tiles.iter().cloned());
+                    }
+                }
+            }
+        }
+        packets
+    }
+}
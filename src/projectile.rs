@@ -0,0 +1,110 @@
+use std::time::{Duration, Instant};
+use crate::astar::is_solid;
+use crate::packet::PlayerData;
+use crate::smoothing::tiles_crossed;
+use crate::vector::Vector;
+
+// Projectiles are simulated on the server and broadcast as:
+//   <prs>uuid,owner,x,y,vx,vy!   fired by enemy owner
+//   <pru>uuid,x,y!               moved
+//   <pri>uuid,x,y,hit!           gone, hit is the username struck, "tile" or "NONE" once out of time
+// A player struck takes the damage through the usual <ph> packet.
+
+// How close a projectile has to pass to a player to hit them, in tiles.
+const HIT_RADIUS: f32 = 0.5f32;
+
+// What a ranged enemy fires and how far away it will fire from. Each attack fires count projectiles
+// spread evenly across spread radians, centred on the target.
+#[derive(Clone, Copy)]
+pub struct Ranged {
+    pub range: f32,
+    pub speed: f32,
+    pub count: usize,
+    pub spread: f32
+}
+
+pub enum Impact {
+    Player(String),
+    Tile,
+    Expired
+}
+
+pub struct Projectile {
+    pub uuid: usize,
+    pub damage: i32,
+    pub position: Vector,
+    velocity: Vector,
+    expires: Instant
+}
+
+impl Projectile {
+    pub fn new(uuid: usize, position: Vector, velocity: Vector, damage: i32, lifetime: Duration) -> Self {
+        Projectile { uuid, damage, position, velocity, expires: Instant::now() + lifetime }
+    }
+
+    pub fn spawn_packet(&self, owner: usize) -> String {
+        format!("<prs>{},{owner},{},{},{},{}!", self.uuid, self.position.x, self.position.y, self.velocity.x, self.velocity.y)
+    }
+
+    // Moves the projectile on. A wall crossed on the way cuts the step short, so only players in front of
+    // it can be hit.
+    pub fn update(&mut self, deltatime: f32, tilemap: &[Vec<usize>], players: &[PlayerData]) -> Option<Impact> {
+        let from: Vector = self.position.clone();
+        let mut to: Vector = &from + &(&self.velocity * deltatime);
+        let solid: Option<[isize; 2]> = tiles_crossed(&from, &to).into_iter().find(|[x, y]| {
+            *x < 0 || *y < 0 || *y as usize >= tilemap.len() || *x as usize >= tilemap[*y as usize].len() || is_solid(tilemap[*y as usize][*x as usize])
+        });
+        if let Some(tile) = solid {
+            to = &from + &(&(&to - &from) * entry(&from, &to, tile));
+        }
+        let travel: Vector = &to - &from;
+        for player in players {
+            // Closest point on this step's segment to the player.
+            let offset: Vector = &player.position - &from;
+            let along: f32 = if travel.magnitude > 0f32 { ((offset.x * travel.x + offset.y * travel.y) / (travel.magnitude * travel.magnitude)).clamp(0f32, 1f32) } else { 0f32 };
+            let closest: Vector = &from + &(&travel * along);
+            if (&player.position - &closest).magnitude <= HIT_RADIUS {
+                self.position = closest;
+                return Some(Impact::Player(player.username.clone()));
+            }
+        }
+        if solid.is_some() {
+            self.position = to;
+            return Some(Impact::Tile);
+        }
+        self.position = to;
+        if Instant::now() >= self.expires {
+            return Some(Impact::Expired);
+        }
+        None
+    }
+}
+
+// How far along the segment it enters the tile, as a fraction. Tiles span half a tile either side of
+// their coordinates.
+fn entry(from: &Vector, to: &Vector, tile: [isize; 2]) -> f32 {
+    let mut enter: f32 = 0f32;
+    for (start, end, centre) in [(from.x, to.x, tile[0] as f32), (from.y, to.y, tile[1] as f32)] {
+        let delta: f32 = end - start;
+        if delta == 0f32 { continue; }
+        let near: f32 = if delta > 0f32 { centre - 0.5f32 } else { centre + 0.5f32 };
+        enter = enter.max((near - start) / delta);
+    }
+    enter.clamp(0f32, 1f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn walls_shield_players_behind_them() {
+        // A wall at x = 3 with a player just behind it, well within one step of the projectile.
+        let tilemap: Vec<Vec<usize>> = (0..3).map(|_| vec![2, 2, 2, 1, 2, 2]).collect();
+        let mut player: PlayerData = PlayerData::new();
+        player.position = Vector::component(4f32, 1f32);
+        let mut projectile: Projectile = Projectile::new(1, Vector::component(1f32, 1f32), Vector::component(40f32, 0f32), 10, Duration::from_secs(1));
+        assert!(matches!(projectile.update(0.1f32, &tilemap, &[player]), Some(Impact::Tile)));
+        assert!((projectile.position.x - 2.5f32).abs() < 0.001f32);
+    }
+}
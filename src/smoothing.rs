@@ -0,0 +1,101 @@
+use crate::astar::{walkable, Ai, Position};
+use crate::navgraph::EdgeKind;
+use crate::pathservice::Route;
+use crate::vector::Vector;
+
+// Every tile the segment between two tile centres touches. Where it passes exactly through a
+// corner both side tiles count, so a straight line can never cut a solid corner.
+pub fn tiles_crossed(from: &Vector, to: &Vector) -> Vec<[isize; 2]> {
+    let (x0, y0): (f32, f32) = (from.x + 0.5f32, from.y + 0.5f32);
+    let (x1, y1): (f32, f32) = (to.x + 0.5f32, to.y + 0.5f32);
+    let (dx, dy): (f32, f32) = (x1 - x0, y1 - y0);
+    let (mut x, mut y): (isize, isize) = (x0.floor() as isize, y0.floor() as isize);
+    let end: [isize; 2] = [x1.floor() as isize, y1.floor() as isize];
+    let step_x: isize = if dx > 0f32 { 1 } else { -1 };
+    let step_y: isize = if dy > 0f32 { 1 } else { -1 };
+    let delta_x: f32 = if dx != 0f32 { 1f32 / dx.abs() } else { f32::INFINITY };
+    let delta_y: f32 = if dy != 0f32 { 1f32 / dy.abs() } else { f32::INFINITY };
+    let mut next_x: f32 = if dx > 0f32 { (x as f32 + 1f32 - x0) / dx } else if dx < 0f32 { (x0 - x as f32) / -dx } else { f32::INFINITY };
+    let mut next_y: f32 = if dy > 0f32 { (y as f32 + 1f32 - y0) / dy } else if dy < 0f32 { (y0 - y as f32) / -dy } else { f32::INFINITY };
+
+    let limit: usize = (dx.abs() + dy.abs()) as usize * 2 + 2;
+    let mut tiles: Vec<[isize; 2]> = vec![[x, y]];
+    while [x, y] != end && tiles.len() <= limit {
+        if (next_x - next_y).abs() < 1e-5f32 {
+            tiles.push([x + step_x, y]);
+            tiles.push([x, y + step_y]);
+            x += step_x;
+            y += step_y;
+            next_x += delta_x;
+            next_y += delta_y;
+        } else if next_x < next_y {
+            x += step_x;
+            next_x += delta_x;
+        } else {
+            y += step_y;
+            next_y += delta_y;
+        }
+        tiles.push([x, y]);
+    }
+    tiles
+}
+
+// Whether an enemy of this Ai could move in a straight line between the two points. The tile it starts
+// on is already occupied so is not checked.
+pub fn line_clear(tilemap: &[Vec<usize>], from: &Vector, to: &Vector, ai: &Ai) -> bool {
+    let start: Position = Position::new(from.x.round() as usize, from.y.round() as usize);
+    tiles_crossed(from, to).into_iter().skip(1).all(|[x, y]| {
+        if x < 0 || y < 0 || y as usize >= tilemap.len() || x as usize >= tilemap[y as usize].len() {
+            return false;
+        }
+        walkable(tilemap, &start, &Position::new(x as usize, y as usize), ai)
+    })
+}
+
+// String-pulls a grid path: each waypoint is dropped when the one after it can be seen from the last
+// waypoint kept.
+pub fn smooth_path(tilemap: &[Vec<usize>], path: Vec<Vector>, ai: &Ai) -> Vec<Vector> {
+    if path.len() < 3 {
+        return path;
+    }
+    let mut smoothed: Vec<Vector> = vec![path[0].clone()];
+    let mut anchor: usize = 0;
+    let mut index: usize = 1;
+    while index < path.len() - 1 {
+        if !line_clear(tilemap, &path[anchor], &path[index + 1], ai) {
+            smoothed.push(path[index].clone());
+            anchor = index;
+        }
+        index += 1;
+    }
+    smoothed.push(path[path.len() - 1].clone());
+    smoothed
+}
+
+// Ground routes keep every jump and fall, since those need their own motion. Only straight runs of
+// walking along one floor or climbing one ladder are merged.
+pub fn smooth_route(tilemap: &[Vec<usize>], route: Route, ai: &Ai) -> Route {
+    if route.moves.is_empty() {
+        return Route { path: smooth_path(tilemap, route.path, ai), moves: vec![] };
+    }
+    let mut path: Vec<Vector> = vec![];
+    let mut moves: Vec<EdgeKind> = vec![];
+    for (point, kind) in route.path.into_iter().zip(route.moves) {
+        let count: usize = path.len();
+        if count >= 2 && kind == moves[count - 1] {
+            let previous: &Vector = &path[count - 2];
+            let merge: bool = match kind {
+                EdgeKind::Walk => previous.y == path[count - 1].y && path[count - 1].y == point.y,
+                EdgeKind::Climb => previous.x == path[count - 1].x && path[count - 1].x == point.x,
+                _ => false
+            };
+            if merge {
+                path[count - 1] = point;
+                continue;
+            }
+        }
+        path.push(point);
+        moves.push(kind);
+    }
+    Route { path, moves }
+}
@@ -1,13 +1,292 @@
-use std::{fs::read_to_string};
+use std::fs::read_to_string;
 use rand::Rng;
-use crate::astar::{Position, astar, Ai};
-use core::mem::replace;
+use rand::rngs::ThreadRng;
+use std::collections::VecDeque;
+use crate::astar::{Position, astar, Ai, is_solid};
+use crate::generator::Generator;
+use crate::hierarchy::RoomGraph;
+use crate::progression::{Door, Key, place_locks};
+use crate::vector::Vector;
+use core::mem::take;
+
+#[derive(Clone)]
+pub struct RoomArea {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl RoomArea {
+    pub fn contains_tile(&self, tile: [usize; 2]) -> bool {
+        tile[0] >= self.x && tile[1] >= self.y && tile[0] < self.x + self.width && tile[1] < self.y + self.height
+    }
+
+    // How far a position is outside the room, or 0 inside it.
+    pub fn distance(&self, position: &Vector) -> f32 {
+        let dx: f32 = (self.x as f32 - position.x).max(position.x - (self.x + self.width - 1) as f32).max(0f32);
+        let dy: f32 = (self.y as f32 - position.y).max(position.y - (self.y + self.height - 1) as f32).max(0f32);
+        (dx * dx + dy * dy).sqrt()
+    }
+}
+
+// The boss room of a floor, with the corridor cells next to it that close off the fight. `tiles` holds
+// what the door tiles replace while the fight is on.
+#[derive(Clone)]
+pub struct Arena {
+    pub room: usize,
+    pub area: RoomArea,
+    pub spawn: [usize; 2],
+    pub doors: Vec<[usize; 2]>,
+    pub tiles: Vec<TileChange>
+}
+
+impl Arena {
+    pub fn contains(&self, position: &Vector) -> bool {
+        let (x, y): (f32, f32) = (position.x.round(), position.y.round());
+        x >= self.area.x as f32 && y >= self.area.y as f32 && x < (self.area.x + self.area.width) as f32 && y < (self.area.y + self.area.height) as f32
+    }
+}
+
+#[derive(Clone)]
+pub struct Connection {
+    pub rooms: [usize; 2],
+    pub path: Vec<Position>
+}
 
 #[derive(Clone)]
 pub struct Tilemap {
     pub tilemap: Vec<Vec<usize>>,
     pub spawn_coordinates: [usize; 2],
-    pub spawn_locations: Vec<[usize; 2]>
+    pub spawn_locations: Vec<[usize; 2]>,
+    pub spawn_room: usize,
+    pub rooms: Vec<RoomArea>,
+    pub connections: Vec<Connection>,
+    pub doors: Vec<Door>,
+    pub keys: Vec<Key>,
+    pub exit: Option<[usize; 2]>,
+    pub arena: Option<Arena>,
+    // Built once the floor is finished, for the hierarchical pathfinder.
+    pub room_graph: RoomGraph
+}
+
+pub const EXIT_TILE: usize = 8;
+// Solid until opened. Doors are closed and opened by swapping their tiles at runtime, which clients
+// hear about as <tu>x,y,tile!.
+pub const DOOR_TILE: usize = 12;
+
+#[derive(Clone)]
+pub struct TileChange {
+    pub position: [usize; 2],
+    pub tile: usize
+}
+
+impl TileChange {
+    pub fn packet(&self) -> String {
+        format!("<tu>{},{},{}!", self.position[0], self.position[1], self.tile)
+    }
+}
+
+pub fn apply_changes(tilemap: &mut [Vec<usize>], changes: &[TileChange]) {
+    for change in changes {
+        if let Some(tile) = tilemap.get_mut(change.position[1]).and_then(|row| row.get_mut(change.position[0])) {
+            *tile = change.tile;
+        }
+    }
+}
+
+pub fn decorate(tile: &mut usize, rng: &mut ThreadRng) {
+    if rng.gen_bool(0.04f64) {
+        *tile = 3;
+    }
+    if rng.gen_bool(0.04f64) {
+        *tile = 7;
+    }
+}
+
+impl Tilemap {
+    pub fn new(tilemap: Vec<Vec<usize>>) -> Self {
+        Tilemap {
+            tilemap,
+            spawn_coordinates: [0, 0],
+            spawn_locations: vec![],
+            spawn_room: 0,
+            rooms: vec![],
+            connections: vec![],
+            doors: vec![],
+            keys: vec![],
+            exit: None,
+            arena: None,
+            room_graph: RoomGraph::default()
+        }
+    }
+
+    fn in_bounds(&self, x: usize, y: usize) -> bool {
+        y < self.tilemap.len() && x < self.tilemap[y].len()
+    }
+
+    // Carves a corridor between two rooms along a corridor A* path. Horizontal corridors are two tiles
+    // tall, vertical ones are two ladders wide, and both get the odd decoration along one side.
+    pub fn carve_corridor(&mut self, start: Position, end: Position, rooms: [usize; 2], vertical: bool, rng: &mut ThreadRng) -> bool {
+        let Some(path) = astar(&self.tilemap, start, end, &Ai::Corridor) else {
+            return false;
+        };
+        let mut cells: Vec<Position> = vec![];
+        for point in &path {
+            let x: usize = point.x.round() as usize;
+            let y: usize = point.y.round() as usize;
+            if vertical {
+                self.tilemap[y][x] = 6;
+                if self.in_bounds(x + 1, y) {
+                    self.tilemap[y][x+1] = 6;
+                }
+                if self.in_bounds(x + 2, y) {
+                    decorate(&mut self.tilemap[y][x+2], rng);
+                }
+            }
+            else {
+                self.tilemap[y][x] = 2;
+                if self.in_bounds(x, y + 1) {
+                    self.tilemap[y+1][x] = 2;
+                    decorate(&mut self.tilemap[y+1][x], rng);
+                }
+            }
+            cells.push(Position::new(x, y));
+        }
+        self.connections.push(Connection { rooms, path: cells });
+        true
+    }
+
+    // The open tiles a door on this corridor cell fills, as they are now. Corridors are at most two
+    // tiles across, so everything open next to the cell is enough to close it off.
+    pub fn door_span(&self, cell: [usize; 2]) -> Vec<TileChange> {
+        let mut tiles: Vec<TileChange> = vec![];
+        for y in cell[1].saturating_sub(1)..=cell[1] + 1 {
+            for x in cell[0].saturating_sub(1)..=cell[0] + 1 {
+                if self.in_bounds(x, y) && !is_solid(self.tilemap[y][x]) {
+                    tiles.push(TileChange { position: [x, y], tile: self.tilemap[y][x] });
+                }
+            }
+        }
+        tiles
+    }
+
+    // The cell of a connection's corridor closest to its middle that lies outside every room.
+    pub fn door_cell(&self, connection: usize) -> Option<[usize; 2]> {
+        let path: &Vec<Position> = &self.connections[connection].path;
+        let middle: usize = path.len() / 2;
+        path.iter().enumerate()
+            .filter(|(_, cell)| !self.rooms.iter().any(|room| room.contains_tile([cell.x, cell.y])))
+            .min_by_key(|(index, _)| index.abs_diff(middle))
+            .map(|(_, cell)| [cell.x, cell.y])
+    }
+
+    pub fn standable_tiles(&self, room: usize) -> Vec<[usize; 2]> {
+        let area: &RoomArea = &self.rooms[room];
+        let mut tiles: Vec<[usize; 2]> = vec![];
+        for y in area.y.max(1)..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                let tile: usize = self.tilemap[y][x];
+                if !is_solid(tile) && tile != 6 && is_solid(self.tilemap[y - 1][x]) {
+                    tiles.push([x, y]);
+                }
+            }
+        }
+        tiles
+    }
+
+    fn room_distances(&self) -> Vec<Option<usize>> {
+        let mut distances: Vec<Option<usize>> = vec![None; self.rooms.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        distances[self.spawn_room] = Some(0);
+        queue.push_back(self.spawn_room);
+        while let Some(room) = queue.pop_front() {
+            let distance: usize = distances[room].unwrap_or(0);
+            for connection in &self.connections {
+                let other: usize = if connection.rooms[0] == room {
+                    connection.rooms[1]
+                } else if connection.rooms[1] == room {
+                    connection.rooms[0]
+                } else {
+                    continue;
+                };
+                if distances[other].is_none() {
+                    distances[other] = Some(distance + 1);
+                    queue.push_back(other);
+                }
+            }
+        }
+        distances
+    }
+
+    fn furthest_room(&self) -> usize {
+        let distances: Vec<Option<usize>> = self.room_distances();
+        let mut furthest: usize = self.spawn_room;
+        for (room, distance) in distances.iter().enumerate() {
+            if let (Some(distance), Some(best)) = (distance, distances[furthest]) {
+                if *distance > best {
+                    furthest = room;
+                }
+            }
+        }
+        furthest
+    }
+
+    // The exit goes in the room furthest from spawn, on the tile furthest from the spawn point.
+    fn place_exit(&mut self) {
+        if self.rooms.is_empty() {
+            return;
+        }
+        let exit_room: usize = self.furthest_room();
+        let spawn: Vector = Vector::from(self.spawn_coordinates);
+        let mut best_distance: f32 = -1.0f32;
+        for tile in self.standable_tiles(exit_room) {
+            if self.keys.iter().any(|key| key.position == tile) { continue; }
+            let distance: f32 = (Vector::from(tile) - &spawn).magnitude;
+            if distance > best_distance {
+                best_distance = distance;
+                self.exit = Some(tile);
+            }
+        }
+        if let Some(exit) = self.exit {
+            self.tilemap[exit[1]][exit[0]] = EXIT_TILE;
+            println!("Exit @ {},{}", exit[0], exit[1]);
+        }
+    }
+
+    // The last floor has no exit, so its furthest room holds the boss instead. Every corridor into
+    // the room gets a door on its last cell.
+    fn place_arena(&mut self) {
+        if self.rooms.len() < 2 {
+            return;
+        }
+        let room: usize = self.furthest_room();
+        if room == self.spawn_room {
+            return;
+        }
+        // Doors go far enough down each corridor that closing them never catches a tile of the arena.
+        let area: &RoomArea = &self.rooms[room];
+        let doors: Vec<[usize; 2]> = self.connections.iter().filter_map(|connection| {
+            let cells: Vec<&Position> = if connection.rooms[0] == room {
+                connection.path.iter().collect()
+            } else if connection.rooms[1] == room {
+                connection.path.iter().rev().collect()
+            } else {
+                return None;
+            };
+            let cell: &Position = cells.iter().find(|cell| area.distance(&Vector::from([cell.x, cell.y])) >= 2f32).or(cells.first())?;
+            Some([cell.x, cell.y])
+        }).collect();
+        let mut tiles: Vec<TileChange> = vec![];
+        for door in &doors {
+            for tile in self.door_span(*door) {
+                if !tiles.iter().any(|other| other.position == tile.position) {
+                    tiles.push(tile);
+                }
+            }
+        }
+        self.arena = Some(Arena { room, area: area.clone(), spawn: self.spawn_locations[room], doors, tiles });
+    }
 }
 
 impl From<Vec<Vec<Room>>> for Tilemap {
@@ -15,45 +294,33 @@ impl From<Vec<Vec<Room>>> for Tilemap {
         let size: usize = room_map.len();
         let mut rng = rand::thread_rng();
         let spawn_room: [usize; 2] = [rng.gen_range(0..size), rng.gen_range(0..size)];
-        let mut tilemap = Tilemap { 
-            tilemap: vec![], 
-            spawn_coordinates: [spawn_room[0] * 32 + 9, (size - spawn_room[1] - 1) * 32 + 2 + (32 - Room::from(0).tilemap.len())],
-            spawn_locations: vec![]
-        };
+        let mut tilemap = Tilemap::new(vec![]);
+        tilemap.spawn_coordinates = [spawn_room[0] * 32 + 9, (size - spawn_room[1] - 1) * 32 + 2 + (32 - Room::from(0).tilemap.len())];
+        tilemap.spawn_room = (size - spawn_room[1] - 1) * size + spawn_room[0];
         println!("Chose spawn location: [{},{}]!", tilemap.spawn_coordinates[0], tilemap.spawn_coordinates[1]);
         let mut raw_tilemap: Vec<Vec<usize>> = vec![];
         room_map[spawn_room[1]][spawn_room[0]] = 0.into();
-        let mut count: usize = 0;
-        for room_row in 0..size {
+        for room_row in &room_map {
             for tile_row in 0..32 {
                 let mut row: Vec<usize> = vec![];
-                for room_column in 0..size {
+                for room in room_row {
                     for tile_column in 0..32 {
-                        if tile_row < room_map[room_row][room_column].tilemap.len() && tile_column < room_map[room_row][room_column].tilemap.len() {
-                            row.push(room_map[room_row][room_column].tilemap[tile_row][tile_column]);
+                        if tile_row < room.tilemap.len() && tile_column < room.tilemap.len() {
+                            row.push(room.tilemap[tile_row][tile_column]);
                         }
                         else {
                             row.push(1);
                         }
                     }
                 }
-                count += 1;
                 raw_tilemap.push(row);
             }
         }
         let final_length: usize = raw_tilemap.len();
         for row in 0..final_length {
-            tilemap.tilemap.push(replace(&mut raw_tilemap[final_length - row - 1], vec![]));
+            tilemap.tilemap.push(take(&mut raw_tilemap[final_length - row - 1]));
         }
 
-        for room_row in 0..size {
-            for room_column in 0..size {
-                tilemap.spawn_locations.push([room_map[room_row][room_column].spawn_x + room_column * 32, room_map[room_row][room_column].spawn_y + room_row * 32 + (32 - room_map[room_row][room_column].tilemap.len())]);
-                if let Some(spawn_loc) = tilemap.spawn_locations.last() {
-                    println!("Spawn location @ {},{} : TILE_ID={}", spawn_loc[0], spawn_loc[1], tilemap.tilemap[spawn_loc[1]][spawn_loc[0]]);
-                }
-            }
-        }
         for row in &room_map {
             for item in row {
                 print!(" {:?}", item.id);
@@ -61,6 +328,21 @@ impl From<Vec<Vec<Room>>> for Tilemap {
             println!();
         }
         room_map.reverse();
+        for (room_row, rooms) in room_map.iter().enumerate() {
+            for (room_column, room) in rooms.iter().enumerate() {
+                let base_y: usize = room_row * 32 + (32 - room.tilemap.len());
+                tilemap.rooms.push(RoomArea {
+                    x: room_column * 32,
+                    y: base_y,
+                    width: room.tilemap.len(),
+                    height: room.tilemap.len()
+                });
+                tilemap.spawn_locations.push([room.spawn_x + room_column * 32, room.spawn_y + base_y]);
+                if let Some(spawn_loc) = tilemap.spawn_locations.last() {
+                    println!("Spawn location @ {},{} : TILE_ID={}", spawn_loc[0], spawn_loc[1], tilemap.tilemap[spawn_loc[1]][spawn_loc[0]]);
+                }
+            }
+        }
         for room_row in 0..size {
             for room_column in 0..size {
                 if room_column < size - 1 {
@@ -69,20 +351,7 @@ impl From<Vec<Vec<Room>>> for Tilemap {
                     if let (Some(this_e), Some(next_e)) = (this_room.entrance_right, next_room.entrance_left) {
                         let start: Position = Position::new(room_column * 32 + this_room.tilemap.len(), room_row * 32 + (32 - this_room.tilemap.len()) + this_e);
                         let end: Position = Position::new(room_column * 32 + 32, room_row * 32 + (32 - next_room.tilemap.len()) + next_e);
-                        if let Some(path) = astar(&tilemap.tilemap, start, end, &Ai::Corridor) {
-                            for point in &path {
-                                let x: usize = point.x.round() as usize;
-                                let y: usize = point.y.round() as usize;
-                                tilemap.tilemap[y][x] = 2;
-                                tilemap.tilemap[y+1][x] = 2;
-                                if rng.gen_bool(0.04f64) {
-                                    tilemap.tilemap[y+1][x] = 3;
-                                }
-                                if rng.gen_bool(0.04f64) {
-                                    tilemap.tilemap[y+1][x] = 7;
-                                }
-                            }
-                        }
+                        tilemap.carve_corridor(start, end, [room_row * size + room_column, room_row * size + room_column + 1], false, &mut rng);
                     }
                 }
                 if room_row < size - 1 {
@@ -91,20 +360,7 @@ impl From<Vec<Vec<Room>>> for Tilemap {
                     if let (Some(this_e), Some(next_e)) = (this_room.entrance_top, next_room.entrance_bottom) {
                         let start: Position = Position::new(room_column * 32 + this_e, room_row * 32 + 31);
                         let end: Position = Position::new(room_column * 32 + next_e, room_row * 32 + 32 + (32 - next_room.tilemap.len()));
-                        if let Some(path) = astar(&tilemap.tilemap, start, end, &Ai::Corridor) {
-                            for point in &path {
-                                let x: usize = point.x.round() as usize;
-                                let y: usize = point.y.round() as usize;
-                                tilemap.tilemap[y][x] = 6;
-                                tilemap.tilemap[y][x+1] = 6;
-                                if rng.gen_bool(0.04f64) {
-                                    tilemap.tilemap[y][x+2] = 3;
-                                }
-                                if rng.gen_bool(0.04f64) {
-                                    tilemap.tilemap[y][x+2] = 7;
-                                }
-                            }
-                        }
+                        tilemap.carve_corridor(start, end, [room_row * size + room_column, (room_row + 1) * size + room_column], true, &mut rng);
                     }
                 }
             }
@@ -153,8 +409,8 @@ impl From<usize> for Room {
                 if let Some(first_line) = lines.first() {
                     width = first_line.len();
                 }
-                for y in 0..width {
-                    room.tilemap.push(replace(&mut lines[y], vec![]));
+                for line in lines.iter_mut().take(width) {
+                    room.tilemap.push(take(line));
                 }
                 for index in 0..lines.len() - width {
                     match index {
@@ -202,3 +458,17 @@ pub fn randomize_rooms(size: usize, stop: usize) -> Vec<Vec<Room>> {
     }
     room_ids
 }
+
+// Deeper floors are generated larger, and every floor but the last gets an exit. The last gets a boss.
+pub fn generate_floor(generator: &dyn Generator, depth: usize, floors: usize) -> Tilemap {
+    let mut rng = rand::thread_rng();
+    let mut tilemap: Tilemap = generator.generate(depth);
+    place_locks(&mut tilemap, &mut rng);
+    if depth + 1 < floors {
+        tilemap.place_exit();
+    } else {
+        tilemap.place_arena();
+    }
+    tilemap.room_graph = RoomGraph::from(&tilemap);
+    tilemap
+}
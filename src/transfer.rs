@@ -0,0 +1,114 @@
+use std::time::{Instant, Duration};
+use crate::packet::{TilemapChunk, CHUNK_SIZE};
+use crate::vector::Vector;
+
+const MAX_IN_FLIGHT: usize = 8;
+const RESEND_AFTER: Duration = Duration::from_millis(750);
+
+// Streams a tilemap to a single client in chunks, closest to the spawn point first.
+// A chunk is resent until the client acknowledges it with <ca>username,chunk_id!.
+pub struct Transfer {
+    header: Vec<String>,
+    chunks: Vec<TilemapChunk>,
+    acked: Vec<bool>,
+    sent: Vec<Option<Instant>>,
+    footer: Vec<String>,
+    started: bool
+}
+
+impl Transfer {
+    pub fn new(header: Vec<String>, mut chunks: Vec<TilemapChunk>, spawn: [usize; 2], footer: Vec<String>) -> Self {
+        let spawn: Vector = Vector::from(spawn);
+        let half: usize = CHUNK_SIZE / 2;
+        chunks.sort_by(|a, b| {
+            let a_distance: f32 = (Vector::from([a.x + half, a.y + half]) - &spawn).magnitude;
+            let b_distance: f32 = (Vector::from([b.x + half, b.y + half]) - &spawn).magnitude;
+            a_distance.total_cmp(&b_distance)
+        });
+        let count: usize = chunks.len();
+        Transfer { header, chunks, acked: vec![false; count], sent: vec![None; count], footer, started: false }
+    }
+
+    pub fn acknowledge(&mut self, chunk_id: usize) {
+        if let Some(index) = self.chunks.iter().position(|chunk| chunk.id == chunk_id) {
+            self.acked[index] = true;
+        }
+    }
+
+    // Swaps in chunks rebuilt after tiles changed mid-transfer. Any that differ from the copy being sent
+    // go out again, so a late resend can never undo the change on the client.
+    pub fn refresh(&mut self, chunks: &[TilemapChunk]) {
+        for (index, chunk) in self.chunks.iter_mut().enumerate() {
+            let Some(current) = chunks.iter().find(|current| current.id == chunk.id) else { continue; };
+            if current.packet != chunk.packet {
+                *chunk = current.clone();
+                self.acked[index] = false;
+                self.sent[index] = None;
+            }
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.started && self.acked.iter().all(|acked| *acked)
+    }
+
+    // Returns the packets due this tick. Once every chunk is acknowledged the footer is returned.
+    pub fn poll(&mut self) -> Vec<String> {
+        let mut packets: Vec<String> = vec![];
+        if !self.started {
+            self.started = true;
+            packets.append(&mut self.header);
+        }
+        let now: Instant = Instant::now();
+        let mut in_flight: usize = 0;
+        for index in 0..self.chunks.len() {
+            if self.acked[index] { continue; }
+            match self.sent[index] {
+                Some(sent) if now.duration_since(sent) < RESEND_AFTER => {
+                    in_flight += 1;
+                }
+                _ => {
+                    if in_flight >= MAX_IN_FLIGHT { break; }
+                    packets.push(self.chunks[index].packet.clone());
+                    self.sent[index] = Some(now);
+                    in_flight += 1;
+                }
+            }
+        }
+        if self.is_finished() {
+            packets.append(&mut self.footer);
+        }
+        packets
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::tilemap_chunks;
+    use crate::tilemap::{apply_changes, Tilemap, TileChange, DOOR_TILE};
+
+    fn chunk_ids(packets: &[String]) -> Vec<usize> {
+        packets.iter().filter_map(|packet| packet.strip_prefix("<tmc>")?.split(',').next()?.parse::<usize>().ok()).collect()
+    }
+
+    #[test]
+    fn tiles_changed_mid_transfer_are_resent() {
+        let mut tilemap: Tilemap = Tilemap::new(vec![vec![2; CHUNK_SIZE * 2]; CHUNK_SIZE]);
+        let mut transfer: Transfer = Transfer::new(vec![], tilemap_chunks(&tilemap), [0, 0], vec![String::from("<done>!")]);
+        assert_eq!(chunk_ids(&transfer.poll()).len(), 2);
+        transfer.acknowledge(0);
+        transfer.acknowledge(1);
+
+        // A door closes in the second chunk before the footer has gone out.
+        apply_changes(&mut tilemap.tilemap, &[TileChange { position: [CHUNK_SIZE + 1, 1], tile: DOOR_TILE }]);
+        let chunks: Vec<TilemapChunk> = tilemap_chunks(&tilemap);
+        transfer.refresh(&chunks);
+        assert!(!transfer.is_finished());
+        let packets: Vec<String> = transfer.poll();
+        assert_eq!(chunk_ids(&packets), vec![1]);
+        assert!(packets.contains(&chunks[1].packet));
+        transfer.acknowledge(1);
+        assert_eq!(transfer.poll(), vec![String::from("<done>!")]);
+    }
+}
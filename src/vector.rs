@@ -16,6 +16,12 @@ impl From<[usize; 2]> for Vector {
     }
 }
 
+impl Default for Vector {
+    fn default() -> Self {
+        Vector::new()
+    }
+}
+
 impl From<Position> for Vector {
     fn from(position: Position) -> Self {
         Vector::component(position.x as f32, position.y as f32)